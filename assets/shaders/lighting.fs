@@ -0,0 +1,109 @@
+#version 330 core
+
+#ifndef MAX_LIGHTS
+#define MAX_LIGHTS 16
+#endif
+
+out vec4 FragColor;
+
+in vec3 Normal;
+in vec3 FragPos;
+in vec2 TexCoords;
+in vec3 ObjectColor;
+in vec4 FragPosLightSpace;
+uniform int lightCount;
+uniform vec3 lightPos[MAX_LIGHTS];
+uniform vec3 lightColor[MAX_LIGHTS];
+uniform vec3 lightAttenuation[MAX_LIGHTS]; // constant, linear, quadratic
+
+uniform vec3 sunDirection; // direction the sunlight travels
+uniform vec3 sunColor;
+
+// Surface properties, the albedo colour comes from the vertex shader so it can be per instance
+struct Material {
+    float ambient;    // ambient strength
+    float specular;   // specular strength, 0 for matte surfaces
+    float shininess;  // specular exponent
+    vec3 emissive;    // added after lighting, makes the surface glow
+    bool textured;    // multiply the albedo by ourTexture
+};
+uniform Material material;
+uniform vec3 viewPos;
+
+uniform sampler2D ourTexture;
+uniform sampler2D shadowMap;
+uniform bool shadowsEnabled;
+
+uniform vec3 fogColor;
+uniform float fogDensity; // exponential squared fog, 0 for none
+
+// Fraction of sunlight blocked at this fragment, 3x3 PCF over the shadow map
+float sunShadow(vec3 norm)
+{
+    if (!shadowsEnabled) {
+        return 0.0;
+    }
+    vec3 coords = FragPosLightSpace.xyz / FragPosLightSpace.w * 0.5 + 0.5;
+    if (coords.z > 1.0) {
+        return 0.0;
+    }
+    // slope scaled bias against shadow acne on the terrain
+    float bias = max(0.005 * (1.0 - dot(norm, -sunDirection)), 0.0005);
+    vec2 texel = 1.0 / vec2(textureSize(shadowMap, 0));
+    float shadow = 0.0;
+    for (int x = -1; x <= 1; ++x) {
+        for (int y = -1; y <= 1; ++y) {
+            float depth = texture(shadowMap, coords.xy + vec2(x, y) * texel).r;
+            shadow += coords.z - bias > depth ? 1.0 : 0.0;
+        }
+    }
+    return shadow / 9.0;
+}
+
+void main()
+{
+    // ambient
+    vec3 ambient = vec3(0.0);
+    vec3 diffuse = vec3(0.0);
+    vec3 specular = vec3(0.0);
+    vec3 norm = normalize(Normal);
+    vec3 viewDir = normalize(viewPos - FragPos);
+    for (int i=0; i<min(lightCount, MAX_LIGHTS); ++i) {
+        float d = length(lightPos[i] - FragPos);
+        vec3 k = lightAttenuation[i];
+        vec3 color = lightColor[i] / (k.x + k.y * d + k.z * d * d);
+        ambient += material.ambient * color;
+        vec3 lightDir = normalize(lightPos[i] - FragPos);
+        float diff = max(dot(norm, lightDir), 0.0);
+        diffuse += diff * color;
+        // Blinn-Phong highlight
+        float spec = pow(max(dot(norm, normalize(lightDir + viewDir)), 0.0), material.shininess);
+        specular += material.specular * spec * color;
+    }
+    ambient += material.ambient * sunColor;
+    float sunLit = 1.0 - sunShadow(norm);
+    float sunDiff = max(dot(norm, -sunDirection), 0.0);
+    float sunSpec = pow(max(dot(norm, normalize(viewDir - sunDirection)), 0.0), material.shininess);
+    diffuse += sunLit * sunDiff * sunColor;
+    specular += sunLit * material.specular * sunSpec * sunColor;
+
+    vec4 albedo = vec4(ObjectColor, 1.0);
+    if (material.textured) {
+        albedo *= texture(ourTexture, TexCoords);
+    }
+    vec3 lit = (ambient + diffuse) * albedo.rgb + specular + material.emissive;
+
+    // distance fog, fades into the sky towards the map edge
+    float fogAmount = fogDensity * length(viewPos - FragPos);
+    float visibility = clamp(exp(-fogAmount * fogAmount), 0.0, 1.0);
+    FragColor = vec4(mix(fogColor, lit, visibility), albedo.a);
+
+    // vec3 ambient = vec3(0.1, 0.1, 0.1); // Ambient lighting
+    // vec3 lightDir = normalize(vec3(0.0,  1.0, 0.0)); // Example light direction
+    // vec3 diffuse = texture(ourTexture, TexCoords).rgb; // Sample texture
+    // vec3 normal = normalize(Normal);
+    // float diff = max(dot(normal, lightDir), 0.0);
+    // vec3 lighting = ambient + diff * diffuse; // Lambertian lighting model
+
+    // FragColor = vec4(lighting, 1.0);
+}
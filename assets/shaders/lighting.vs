@@ -0,0 +1,37 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec3 aTexCoord;
+#ifdef INSTANCED
+// per instance attributes, the model matrix takes locations 3 to 6
+layout (location = 3) in mat4 aModel;
+layout (location = 7) in vec3 aColor;
+#endif
+
+out vec3 Normal;
+out vec3 FragPos;
+out vec2 TexCoords;
+out vec3 ObjectColor;
+out vec4 FragPosLightSpace;
+
+uniform mat4 model;
+uniform mat4 proj;
+uniform mat4 view;
+uniform vec3 objectColor;
+uniform mat4 lightSpace;
+
+void main()
+{
+#ifdef INSTANCED
+    mat4 modelMat = aModel;
+    ObjectColor = aColor;
+#else
+    mat4 modelMat = model;
+    ObjectColor = objectColor;
+#endif
+    FragPos = vec3(modelMat * vec4(aPos, 1.0));
+    Normal = mat3(transpose(inverse(modelMat))) * aNormal;
+    TexCoords = aTexCoord.xy;
+    FragPosLightSpace = lightSpace * vec4(FragPos, 1.0);
+    gl_Position = proj * view * vec4(FragPos, 1.0);
+}
@@ -0,0 +1,19 @@
+#version 330 core
+// Depth only pass rendering the scene from the sun into the shadow map
+layout (location = 0) in vec3 aPos;
+#ifdef INSTANCED
+layout (location = 3) in mat4 aModel;
+#endif
+
+uniform mat4 model;
+uniform mat4 lightSpace;
+
+void main()
+{
+#ifdef INSTANCED
+    mat4 modelMat = aModel;
+#else
+    mat4 modelMat = model;
+#endif
+    gl_Position = lightSpace * modelMat * vec4(aPos, 1.0);
+}
@@ -0,0 +1,12 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec3 TexCoords;
+
+uniform samplerCube skybox;
+uniform vec3 skyTint;
+
+void main()
+{
+    FragColor = vec4(texture(skybox, TexCoords).rgb * skyTint, 1.0);
+}
@@ -0,0 +1,16 @@
+#version 330 core
+// Unit cube around the camera, drawn last at the far plane
+layout (location = 0) in vec3 aPos;
+
+out vec3 TexCoords;
+
+uniform mat4 proj;
+uniform mat4 view;
+
+void main()
+{
+    TexCoords = aPos;
+    // rotation only, the sky does not move with the camera
+    vec4 pos = proj * mat4(mat3(view)) * vec4(aPos, 1.0);
+    gl_Position = pos.xyww;
+}
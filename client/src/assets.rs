@@ -0,0 +1,469 @@
+extern crate gl;
+use crate::meshloader::{Mesh, MeshError};
+use gl::types::{GLint, GLuint};
+use glam::{vec3a, Vec3A};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+// Environment variable that overrides the asset root directory
+pub const ASSETS_ENV_VAR: &str = "SEPHERE_ASSETS";
+const ASSETS_DIR: &str = "assets";
+const TEXTURE_DIR: &str = "textures";
+const TEXTURE_EXTENSIONS: [&str; 2] = ["jpg", "png"];
+const HOT_RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+// Cubemap face file names, in GL_TEXTURE_CUBE_MAP_POSITIVE_X + i order
+const CUBEMAP_FACES: [&str; 6] = ["right", "left", "top", "bottom", "front", "back"];
+
+// Error returned when an asset cannot be found or loaded
+#[derive(Debug)]
+pub enum AssetError {
+    NoRoot { searched: Vec<PathBuf> },
+    Missing { name: String, root: PathBuf },
+    Mesh(MeshError),
+    Texture { path: PathBuf, reason: String },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetError::NoRoot { searched } => {
+                write!(f, "Asset directory not found, searched:")?;
+                for path in searched {
+                    write!(f, " {}", path.display())?;
+                }
+                write!(f, " (set {} to the assets folder)", ASSETS_ENV_VAR)
+            }
+            AssetError::Missing { name, root } => write!(
+                f,
+                "Asset not found: {} (asset root: {}, set {} to override)",
+                name,
+                root.display(),
+                ASSETS_ENV_VAR
+            ),
+            AssetError::Mesh(e) => write!(f, "{}", e),
+            AssetError::Texture { path, reason } => {
+                write!(f, "Texture File: {} could not be loaded: {}", path.display(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+impl From<MeshError> for AssetError {
+    fn from(e: MeshError) -> Self {
+        AssetError::Mesh(e)
+    }
+}
+
+// Allows asset loading to be propagated with `?` inside the game loop
+impl From<AssetError> for std::io::Error {
+    fn from(e: AssetError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::NotFound, e.to_string())
+    }
+}
+
+// Handle to a GL texture object owned by the asset manager
+pub type TextureHandle = GLuint;
+
+// Asset manager, resolves asset paths against the asset root and caches loaded meshes and
+// textures so every entity using the same file shares one copy
+pub struct Assets {
+    pub root: PathBuf,
+    meshes: HashMap<PathBuf, Rc<Mesh>>,
+    textures: HashMap<PathBuf, TextureHandle>,
+    cubemaps: HashMap<PathBuf, TextureHandle>,
+    gradient: Option<TextureHandle>, // sky gradient, reused when the map changes
+    watcher: Option<FileWatcher>, // Some in dev mode, see enable_hot_reload
+}
+
+// Polls the modification time of watched files, used for hot reloading in dev mode
+struct FileWatcher {
+    files: HashMap<PathBuf, Option<SystemTime>>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    // Method to start watching a file, recording its current modification time
+    fn watch(&mut self, path: &Path) {
+        if !self.files.contains_key(path) {
+            self.files.insert(path.to_path_buf(), modified(path));
+        }
+    }
+
+    // Method to get the files modified since the last poll
+    // Polls at most once per HOT_RELOAD_INTERVAL so it can be called every frame
+    fn changed(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < HOT_RELOAD_INTERVAL {
+            return vec!();
+        }
+        self.last_poll = Instant::now();
+        let mut changed = vec!();
+        for (path, last) in &mut self.files {
+            let now = modified(path);
+            if now != *last {
+                *last = now;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+// Helper to get the modification time of a file, None if it cannot be read (e.g. mid-save)
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Assets {
+    // Asset manager constructor, locating the asset root in this order:
+    // 1. the SEPHERE_ASSETS environment variable
+    // 2. an assets folder next to the executable
+    // 3. an assets folder in any parent of the executable (the workspace, for target/release builds)
+    // 4. an assets folder in the working directory
+    pub fn new() -> Result<Self, AssetError> {
+        Ok(Assets::with_root(find_root()?))
+    }
+
+    // Asset manager constructor with an explicit root
+    pub fn with_root(root: PathBuf) -> Self {
+        Assets {
+            root,
+            meshes: HashMap::new(),
+            textures: HashMap::new(),
+            cubemaps: HashMap::new(),
+            gradient: None,
+            watcher: None,
+        }
+    }
+
+    // Method to turn on dev mode hot reloading for loaded and watched files
+    pub fn enable_hot_reload(&mut self) {
+        let mut watcher = FileWatcher {
+            files: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+        for path in self.meshes.keys().chain(self.textures.keys()) {
+            watcher.watch(path);
+        }
+        self.watcher = Some(watcher);
+    }
+
+    // Method to watch an extra file (e.g. a shader) for changes, no-op outside dev mode
+    pub fn watch(&mut self, path: &Path) {
+        if let Some(watcher) = &mut self.watcher {
+            watcher.watch(path);
+        }
+    }
+
+    /// Method to reload changed files in dev mode
+    /// Textures are re-uploaded in place so entities pick them up automatically, meshes are
+    /// replaced in the cache and entities must swap to the new copy (see Entity::reload_mesh).
+    /// Returns every changed path that was reloaded or is not managed here (e.g. shaders),
+    /// files that fail to reload are reported and keep their old data.
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn poll_changes(&mut self) -> Vec<PathBuf> {
+        let Some(watcher) = &mut self.watcher else {
+            return vec!();
+        };
+        let mut changed = watcher.changed();
+        changed.retain(|path| {
+            if self.meshes.contains_key(path) {
+                match Mesh::new(&path.to_string_lossy(), vec3a(1.0, 1.0, 1.0)) {
+                    Ok(mesh) => {
+                        println!("Reloaded mesh {}", path.display());
+                        self.meshes.insert(path.clone(), Rc::new(mesh));
+                        true
+                    }
+                    Err(e) => {
+                        println!("Mesh reload failed, keeping old mesh: {}", e);
+                        false
+                    }
+                }
+            } else if let Some(&texture) = self.textures.get(path) {
+                match upload_texture(texture, path) {
+                    Ok(()) => {
+                        println!("Reloaded texture {}", path.display());
+                        true
+                    }
+                    Err(e) => {
+                        println!("Texture reload failed, keeping old texture: {}", e);
+                        false
+                    }
+                }
+            } else {
+                true
+            }
+        });
+        changed
+    }
+
+    // Method to get a cached mesh by its resolved path (as stored in Mesh::path)
+    pub fn cached_mesh(&self, path: &Path) -> Option<Rc<Mesh>> {
+        self.meshes.get(path).cloned()
+    }
+
+    // Method to resolve an asset name (e.g. "mesh/ground.stl") to a path that exists
+    pub fn resolve(&self, name: &str) -> Result<PathBuf, AssetError> {
+        let path = self.root.join(name);
+        if path.exists() {
+            Ok(path)
+        } else {
+            Err(AssetError::Missing {
+                name: String::from(name),
+                root: self.root.clone(),
+            })
+        }
+    }
+
+    // Method to get a mesh by name, loading it on first use
+    // The returned handle is shared, entities that deform it get their own copy on write
+    pub fn mesh(&mut self, name: &str) -> Result<Rc<Mesh>, AssetError> {
+        let path = self.resolve(name)?;
+        if let Some(mesh) = self.meshes.get(&path) {
+            return Ok(mesh.clone());
+        }
+        let mesh = Rc::new(Mesh::new(&path.to_string_lossy(), vec3a(1.0, 1.0, 1.0))?);
+        self.watch(&path);
+        self.meshes.insert(path, mesh.clone());
+        Ok(mesh)
+    }
+
+    // Method to resolve a texture name in assets/textures, the extension is optional
+    pub fn texture_path(&self, name: &str) -> Result<PathBuf, AssetError> {
+        let dir = Path::new(TEXTURE_DIR);
+        if Path::new(name).extension().is_some() {
+            return self.resolve(&dir.join(name).to_string_lossy());
+        }
+        for extension in TEXTURE_EXTENSIONS {
+            let file = dir.join(name).with_extension(extension);
+            if let Ok(path) = self.resolve(&file.to_string_lossy()) {
+                return Ok(path);
+            }
+        }
+        Err(AssetError::Missing {
+            name: format!("{}/{}.{{{}}}", TEXTURE_DIR, name, TEXTURE_EXTENSIONS.join(",")),
+            root: self.root.clone(),
+        })
+    }
+
+    /// Method to get a texture by name, decoding and uploading it on first use
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn texture(&mut self, name: &str) -> Result<TextureHandle, AssetError> {
+        let path = self.texture_path(name)?;
+        self.texture_file(&path)
+    }
+
+    /// Method to get a texture by file path (e.g. referenced by a mesh material)
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn texture_file(&mut self, path: &Path) -> Result<TextureHandle, AssetError> {
+        if let Some(&texture) = self.textures.get(path) {
+            return Ok(texture);
+        }
+        if !path.exists() {
+            return Err(AssetError::Missing {
+                name: path.to_string_lossy().into_owned(),
+                root: self.root.clone(),
+            });
+        }
+        let mut texture = 0;
+        gl::GenTextures(1, &mut texture);
+        upload_texture(texture, path)?;
+        self.watch(path);
+        self.textures.insert(path.to_path_buf(), texture);
+        Ok(texture)
+    }
+
+    /// Method to get a cubemap by name, loading assets/textures/<name>/{right,left,top,bottom,
+    /// front,back}.{jpg,png} on first use
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn cubemap(&mut self, name: &str) -> Result<TextureHandle, AssetError> {
+        let dir = self.resolve(&Path::new(TEXTURE_DIR).join(name).to_string_lossy())?;
+        if let Some(&cubemap) = self.cubemaps.get(&dir) {
+            return Ok(cubemap);
+        }
+        let mut faces: Vec<(i32, Vec<u8>)> = vec!();
+        for face in CUBEMAP_FACES {
+            let path = self.texture_path(&format!("{}/{}", name, face))?;
+            let data = image::open(&path)
+                .map_err(|e| AssetError::Texture {
+                    path: path.clone(),
+                    reason: e.to_string(),
+                })?
+                .to_rgb8();
+            // GL reads size * size texels of every face
+            let size = faces.first().map_or(data.width() as i32, |(size, _)| *size);
+            if data.width() != data.height() || data.width() as i32 != size {
+                return Err(AssetError::Texture {
+                    path,
+                    reason: format!(
+                        "cubemap faces must be square and the same size, got {}x{} instead of {}x{}",
+                        data.width(),
+                        data.height(),
+                        size,
+                        size
+                    ),
+                });
+            }
+            faces.push((size, data.into_raw()));
+        }
+        let mut cubemap = 0;
+        gl::GenTextures(1, &mut cubemap);
+        upload_cubemap(cubemap, &faces);
+        self.cubemaps.insert(dir, cubemap);
+        Ok(cubemap)
+    }
+
+    /// Method to generate a sky gradient cubemap, used when a map has no skybox images
+    /// Colours blend from `horizon` at and below the horizon to `zenith` straight up
+    /// Every call draws into the same texture, so reloading the map doesn't leak one
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn gradient_cubemap(&mut self, zenith: Vec3A, horizon: Vec3A, size: usize) -> TextureHandle {
+        let cubemap = *self.gradient.get_or_insert_with(|| {
+            let mut cubemap = 0;
+            gl::GenTextures(1, &mut cubemap);
+            cubemap
+        });
+        upload_cubemap(cubemap, &gradient_faces(zenith, horizon, size));
+        cubemap
+    }
+}
+
+// Helper to draw the six faces of a sky gradient
+fn gradient_faces(zenith: Vec3A, horizon: Vec3A, size: usize) -> Vec<(i32, Vec<u8>)> {
+    let mut faces = vec!();
+    for face in 0..CUBEMAP_FACES.len() {
+        let mut data = Vec::with_capacity(size * size * 3);
+        for row in 0..size {
+            for col in 0..size {
+                // texel centre in [-1, 1], t runs down the face as in the GL cubemap convention
+                let s = 2.0 * (col as f32 + 0.5) / size as f32 - 1.0;
+                let t = 2.0 * (row as f32 + 0.5) / size as f32 - 1.0;
+                let dir = match face {
+                    0 => vec3a(1.0, -t, -s),
+                    1 => vec3a(-1.0, -t, s),
+                    2 => vec3a(s, 1.0, t),
+                    3 => vec3a(s, -1.0, -t),
+                    4 => vec3a(s, -t, 1.0),
+                    _ => vec3a(-s, -t, -1.0),
+                };
+                let up = dir.normalize().y.max(0.0).sqrt();
+                let color = horizon.lerp(zenith, up) * 255.0;
+                data.extend_from_slice(&[color.x as u8, color.y as u8, color.z as u8]);
+            }
+        }
+        faces.push((size as i32, data));
+    }
+    faces
+}
+
+// Helper to upload six square RGB faces into an existing GL cubemap texture
+// Each face must hold size * size texels
+unsafe fn upload_cubemap(cubemap: GLuint, faces: &[(i32, Vec<u8>)]) {
+    gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    for (i, (size, data)) in faces.iter().enumerate() {
+        gl::TexImage2D(
+            gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
+            0,
+            gl::RGB as i32,
+            *size,
+            *size,
+            0,
+            gl::RGB,
+            gl::UNSIGNED_BYTE,
+            data.as_ptr() as *const _,
+        );
+    }
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as GLint);
+}
+
+// Helper to decode an image file into an existing GL texture object
+unsafe fn upload_texture(texture: GLuint, path: &Path) -> Result<(), AssetError> {
+    let data = image::open(path)
+        .map_err(|e| AssetError::Texture {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?
+        .flipv()
+        .to_rgb8();
+    let (width, height) = data.dimensions();
+    gl::ActiveTexture(gl::TEXTURE0);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexParameteri(
+        gl::TEXTURE_2D,
+        gl::TEXTURE_WRAP_S,
+        gl::MIRRORED_REPEAT as GLint,
+    );
+    gl::TexParameteri(
+        gl::TEXTURE_2D,
+        gl::TEXTURE_WRAP_T,
+        gl::MIRRORED_REPEAT as GLint,
+    );
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    gl::TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        gl::RGB as i32,
+        width as GLint,
+        height as GLint,
+        0,
+        gl::RGB,
+        gl::UNSIGNED_BYTE,
+        data.as_ptr() as *const _,
+    );
+    gl::GenerateMipmap(gl::TEXTURE_2D);
+    Ok(())
+}
+
+// Helper to locate the asset root, see Assets::new for the search order
+fn find_root() -> Result<PathBuf, AssetError> {
+    let mut searched = vec!();
+
+    if let Some(dir) = std::env::var_os(ASSETS_ENV_VAR) {
+        let dir = PathBuf::from(dir);
+        if dir.is_dir() {
+            return Ok(dir);
+        }
+        searched.push(dir);
+    }
+
+    if let Ok(exe) = std::env::current_exe() {
+        for dir in exe.ancestors().skip(1) {
+            let candidate = dir.join(ASSETS_DIR);
+            if candidate.is_dir() {
+                return Ok(candidate);
+            }
+            searched.push(candidate);
+        }
+    }
+
+    if let Ok(cwd) = std::env::current_dir() {
+        let candidate = cwd.join(ASSETS_DIR);
+        if candidate.is_dir() {
+            return Ok(candidate);
+        }
+        searched.push(candidate);
+    }
+
+    Err(AssetError::NoRoot { searched })
+}
@@ -0,0 +1,297 @@
+use crate::assets::Assets;
+use crate::settings::Settings;
+use glam::Vec3A;
+use rand::Rng;
+use rodio::buffer::SamplesBuffer;
+use rodio::source::{Buffered, Done, Source};
+use rodio::{Decoder, OutputStream, OutputStreamHandle, SpatialSink};
+use std::f32::consts::PI;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const MAX_VOICES: usize = 24; // effects playing at once, more are dropped
+const SAMPLE_RATE: u32 = 44100; // of the generated sounds
+const WORLD_SCALE: f32 = 0.5; // sounds are full volume within 1 / WORLD_SCALE units and fall off with distance squared
+const EAR_SPACING: f32 = 0.2;
+const RUMBLE_VOICES: usize = 4;
+const RUMBLE_MERGE_DISTANCE: f32 = 1.5; // edits this close together share a rumble
+const RUMBLE_DECAY_RATE: f32 = 3.0; // volume lost per second once editing stops
+
+type Clip = Buffered<SamplesBuffer<f32>>;
+
+// Sound effect, loaded or generated once when the game starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sound {
+    Goal,
+    Fall,
+    Roll,   // looped, for spheres rolling on the ground
+    Impact, // spheres hitting each other
+    Rumble, // looped, for ground being raised or lowered
+}
+
+impl Sound {
+    pub const ALL: [Sound; 5] = [Sound::Goal, Sound::Fall, Sound::Roll, Sound::Impact, Sound::Rumble];
+
+    // Getter for the sound's file in the assets folder, None for sounds that are generated
+    pub fn path(self) -> Option<&'static str> {
+        match self {
+            Sound::Goal => Some("sounds/yay.mp3"),
+            Sound::Fall => Some("sounds/oof.mp3"),
+            Sound::Roll | Sound::Impact | Sound::Rumble => None,
+        }
+    }
+}
+
+// Volume levels from 0 to 1, effects and music are both scaled by master
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Volume {
+    pub master: f32,
+    pub sfx: f32,
+    pub music: f32,
+}
+
+impl Volume {
+    // Getter for the final volume of sound effects
+    pub fn sfx(&self) -> f32 {
+        self.master * self.sfx
+    }
+
+    // Getter for the final volume of music
+    pub fn music(&self) -> f32 {
+        self.master * self.music
+    }
+}
+
+// Looping sound that follows something around, such as a rolling sphere
+pub struct Emitter {
+    sink: Option<SpatialSink>, // None without an audio device
+}
+
+// Audio manager, owns the output device and the decoded sound effects
+// Without an audio device, or with a sound that failed to load, playing does nothing
+pub struct Audio {
+    output: Option<(OutputStream, OutputStreamHandle)>,
+    clips: Vec<Option<Clip>>, // indexed like Sound::ALL
+    voices: Arc<AtomicUsize>, // effects still playing
+    ears: [[f32; 3]; 2],      // left and right, in scaled world space
+    pub volume: Volume,
+}
+
+impl Audio {
+    // Audio constructor, opens the default output device and decodes every sound
+    pub fn new(assets: &Assets, settings: &Settings) -> Self {
+        let output = match OutputStream::try_default() {
+            Ok(output) => Some(output),
+            Err(e) => {
+                println!("No audio device, playing without sound: {}", e);
+                None
+            }
+        };
+        let clips = match output {
+            Some(_) => Sound::ALL.iter().map(|s| load_clip(assets, *s)).collect(),
+            None => vec!(),
+        };
+        Audio {
+            output,
+            clips,
+            voices: Arc::new(AtomicUsize::new(0)),
+            ears: [[-0.5 * EAR_SPACING, 0.0, 0.0], [0.5 * EAR_SPACING, 0.0, 0.0]],
+            volume: Volume {
+                master: settings.master_volume.clamp(0.0, 1.0),
+                sfx: settings.sfx_volume.clamp(0.0, 1.0),
+                music: settings.music_volume.clamp(0.0, 1.0),
+            },
+        }
+    }
+
+    // Getter for the output device, None when playing without sound
+    pub fn handle(&self) -> Option<&OutputStreamHandle> {
+        self.output.as_ref().map(|(_, handle)| handle)
+    }
+
+    // Helper to get a loaded sound, None without an audio device or if it failed to load
+    fn clip(&self, sound: Sound) -> Option<&Clip> {
+        self.clips.get(sound as usize).and_then(Option::as_ref)
+    }
+
+    // Method to move the listener, called every frame with the camera's eye
+    pub fn set_listener(&mut self, eye: Vec3A, right: Vec3A) {
+        let right = right.normalize_or_zero();
+        self.ears = [
+            scaled(eye - 0.5 * EAR_SPACING * right),
+            scaled(eye + 0.5 * EAR_SPACING * right),
+        ];
+    }
+
+    // Method to start a sound effect, returns straight away and mixes with anything already playing
+    pub fn play(&self, sound: Sound) {
+        let (Some(handle), Some(clip)) = (self.handle(), self.clip(sound)) else {
+            return;
+        };
+        let volume = self.volume.sfx();
+        if volume <= 0.0 || self.voices.load(Ordering::Relaxed) >= MAX_VOICES {
+            return;
+        }
+        self.voices.fetch_add(1, Ordering::Relaxed);
+        let source = Done::new(clip.clone().amplify(volume), self.voices.clone());
+        if let Err(e) = handle.play_raw(source.convert_samples()) {
+            println!("Could not play {:?}: {}", sound, e);
+        }
+    }
+
+    // Method to start a sound effect at a position in the world, `volume` is from 0 to 1 on top of
+    // the SFX volume
+    pub fn play_at(&self, sound: Sound, pos: Vec3A, volume: f32) {
+        let (Some(handle), Some(clip)) = (self.handle(), self.clip(sound)) else {
+            return;
+        };
+        let volume = volume * self.volume.sfx();
+        if volume <= 0.0 || self.voices.load(Ordering::Relaxed) >= MAX_VOICES {
+            return;
+        }
+        let Ok(sink) = SpatialSink::try_new(handle, scaled(pos), self.ears[0], self.ears[1]) else {
+            return;
+        };
+        self.voices.fetch_add(1, Ordering::Relaxed);
+        sink.set_volume(volume);
+        sink.append(Done::new(clip.clone(), self.voices.clone()));
+        sink.detach();
+    }
+
+    // Method to start a looping sound, silent until it is updated
+    pub fn emitter(&self, sound: Sound) -> Emitter {
+        let (Some(handle), Some(clip)) = (self.handle(), self.clip(sound)) else {
+            return Emitter { sink: None };
+        };
+        let sink = SpatialSink::try_new(handle, self.ears[0], self.ears[0], self.ears[1]).ok();
+        if let Some(sink) = &sink {
+            sink.set_volume(0.0);
+            sink.append(clip.clone().repeat_infinite());
+        }
+        Emitter { sink }
+    }
+
+    // Method to move a looping sound and set its volume from 0 to 1 and its playback speed
+    pub fn update_emitter(&self, emitter: &Emitter, pos: Vec3A, volume: f32, speed: f32) {
+        let Some(sink) = &emitter.sink else {
+            return;
+        };
+        sink.set_emitter_position(scaled(pos));
+        sink.set_left_ear_position(self.ears[0]);
+        sink.set_right_ear_position(self.ears[1]);
+        sink.set_volume(volume.clamp(0.0, 1.0) * self.volume.sfx());
+        sink.set_speed(speed);
+    }
+}
+
+// Looping rumbles where the ground is being changed
+// Each edit turns up the nearest rumble, which fades out again once editing stops there
+pub struct Rumbles {
+    voices: Vec<(Emitter, Vec3A, f32)>, // emitter, position, volume
+}
+
+impl Rumbles {
+    // Rumbles constructor
+    pub fn new(audio: &Audio) -> Self {
+        Rumbles {
+            voices: (0..RUMBLE_VOICES).map(|_| (audio.emitter(Sound::Rumble), Vec3A::ZERO, 0.0)).collect(),
+        }
+    }
+
+    // Method to rumble at an edited point, `strength` from 0 to 1
+    pub fn edit(&mut self, pos: Vec3A, strength: f32) {
+        let near = self
+            .voices
+            .iter()
+            .position(|(_, at, volume)| *volume > 0.0 && at.distance(pos) < RUMBLE_MERGE_DISTANCE);
+        let quietest = || {
+            (0..self.voices.len())
+                .min_by(|a, b| self.voices[*a].2.total_cmp(&self.voices[*b].2))
+                .unwrap_or(0)
+        };
+        let i = near.unwrap_or_else(quietest);
+        let voice = &mut self.voices[i];
+        voice.1 = pos;
+        voice.2 = voice.2.max(strength.clamp(0.0, 1.0));
+    }
+
+    // Method to fade the rumbles and move them to where they are, called every frame
+    pub fn update(&mut self, audio: &Audio, dt: f32) {
+        for (emitter, pos, volume) in &mut self.voices {
+            *volume = (*volume - RUMBLE_DECAY_RATE * dt).max(0.0);
+            audio.update_emitter(emitter, *pos, *volume, 1.0);
+        }
+    }
+}
+
+// Helper to convert a world position to the space sounds are positioned in
+fn scaled(pos: Vec3A) -> [f32; 3] {
+    (WORLD_SCALE * pos).into()
+}
+
+// Helper to read and fully decode or generate a sound, so nothing is decoded while the game is running
+fn load_clip(assets: &Assets, sound: Sound) -> Option<Clip> {
+    let Some(name) = sound.path() else {
+        return Some(SamplesBuffer::new(1, SAMPLE_RATE, synthesize(sound)).buffered());
+    };
+    let decoder = assets
+        .resolve(name)
+        .map_err(|e| e.to_string())
+        .and_then(|path| std::fs::read(path).map_err(|e| e.to_string()))
+        .and_then(|bytes| Decoder::new(Cursor::new(bytes)).map_err(|e| e.to_string()));
+    match decoder {
+        Ok(decoder) => {
+            let (channels, rate) = (decoder.channels(), decoder.sample_rate());
+            let samples: Vec<f32> = decoder.convert_samples().collect();
+            Some(SamplesBuffer::new(channels, rate, samples).buffered())
+        }
+        Err(e) => {
+            println!("Could not load sound {}: {}", name, e);
+            None
+        }
+    }
+}
+
+// Helper to generate the mono samples of the sounds that have no file
+fn synthesize(sound: Sound) -> Vec<f32> {
+    let mut rng = rand::thread_rng();
+    let noise = |cutoff: f32| {
+        // white noise through a one pole low pass filter
+        let a = 1.0 - f32::exp(-2.0 * PI * cutoff / SAMPLE_RATE as f32);
+        let mut y = 0.0;
+        move |rng: &mut rand::rngs::ThreadRng| {
+            y += a * (rng.gen_range(-1.0..1.0) - y);
+            y
+        }
+    };
+    let seconds = |s: f32| (s * SAMPLE_RATE as f32) as usize;
+    let t = |i: usize| i as f32 / SAMPLE_RATE as f32;
+    match sound {
+        Sound::Roll => {
+            let mut low = noise(300.0);
+            (0..seconds(1.0)).map(|i| {
+                let wobble = 0.8 + 0.2 * f32::sin(2.0 * PI * 3.0 * t(i));
+                2.5 * wobble * low(&mut rng)
+            })
+            .collect()
+        }
+        Sound::Impact => {
+            let mut low = noise(1200.0);
+            (0..seconds(0.3)).map(|i| {
+                let decay = f32::exp(-t(i) * 18.0);
+                decay * (0.7 * f32::sin(2.0 * PI * 140.0 * t(i)) + 0.8 * low(&mut rng))
+            })
+            .collect()
+        }
+        Sound::Rumble => {
+            let mut low = noise(90.0);
+            (0..seconds(1.0)).map(|i| {
+                let hum = 0.3 * f32::sin(2.0 * PI * 45.0 * t(i));
+                hum + 5.0 * low(&mut rng)
+            })
+            .collect()
+        }
+        Sound::Goal | Sound::Fall => vec!(),
+    }
+}
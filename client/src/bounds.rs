@@ -0,0 +1,59 @@
+use glam::{Mat4, Vec3A, Vec4, Vec4Swizzles};
+
+// Bounding sphere used for visibility culling
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3A,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // Bounding sphere constructor around the bounding box centre of some points
+    pub fn from_points(points: &[Vec3A]) -> Self {
+        if points.is_empty() {
+            return BoundingSphere {
+                center: Vec3A::ZERO,
+                radius: 0.0,
+            };
+        }
+        let min = points.iter().fold(Vec3A::splat(f32::MAX), |a, &v| a.min(v));
+        let max = points.iter().fold(Vec3A::splat(f32::MIN), |a, &v| a.max(v));
+        let center = 0.5 * (min + max);
+        let radius = points.iter().map(|&v| v.distance(center)).fold(0.0, f32::max);
+        BoundingSphere { center, radius }
+    }
+
+    // Method to get the sphere enclosing this one after a model transform
+    // The radius is grown by the largest scale factor so it stays conservative
+    pub fn transformed(&self, model: &Mat4) -> Self {
+        let (scale, _, _) = model.to_scale_rotation_translation();
+        BoundingSphere {
+            center: model.transform_point3a(self.center),
+            radius: self.radius * scale.abs().max_element(),
+        }
+    }
+}
+
+// View frustum as six inward facing planes (xyz normal, w distance), in world space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // Frustum constructor from a view-projection matrix (proj * view)
+    // Planes are extracted from the matrix rows (Gribb/Hartmann) and normalized
+    pub fn from_matrix(view_proj: Mat4) -> Self {
+        let (r0, r1, r2, r3) = (view_proj.row(0), view_proj.row(1), view_proj.row(2), view_proj.row(3));
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r3 + r2, r3 - r2]
+            .map(|p| p / p.xyz().length().max(f32::EPSILON));
+        Frustum { planes }
+    }
+
+    // Method to check if any part of a sphere may be inside the frustum
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|p| Vec3A::from(p.xyz()).dot(sphere.center) + p.w >= -sphere.radius)
+    }
+}
@@ -3,9 +3,21 @@ extern crate glam;
 use glam::f32::{Mat4, Vec3A};
 use glam::vec3a;
 use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use crate::settings::Settings;
 use crate::Entity;
 
+// Furthest first person and spectator views look up or down, short of straight up to keep the
+// up vector valid
+const LOOK_LIMIT: f32 = 1.5;
+const ZOOM_RATE: f32 = 10.0; // per second, how quickly zoom catches up with the scroll wheel
+const OCCLUSION_MARGIN: f32 = 0.1; // kept between the eye and whatever occludes it, clear of the near plane
+const MIN_OCCLUSION: f32 = 0.05; // the eye is never pulled closer than this fraction
+const OCCLUSION_RELEASE_RATE: f32 = 3.0; // per second, easing back out once the view is clear
+
 // Abstract Struct
+#[derive(Clone)]
 pub struct CameraBare {
     // For use in camera
     pub fov: f32,
@@ -18,20 +30,283 @@ impl CameraBare {
     //get a projection matrix
     fn proj_mat(&mut self) -> Mat4 {
         Mat4::perspective_rh(
-            self.fov.into(),
-            self.aspect.into(),
-            self.near.into(),
-            self.far.into(),
+            self.fov,
+            self.aspect,
+            self.near,
+            self.far,
         )
     }
 }
 
+// Camera mode, which controller places the eye
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    Orbit,       // circles the player at camera_angle and tilt
+    Chase,       // swings in behind the direction the player rolls
+    FirstPerson, // looks out from the top of the sphere
+    Spectator,   // flies freely, detached from the player
+}
+
+impl CameraMode {
+    // Method to get the mode after this one, for cycling through the modes with a key
+    pub fn next(self) -> Self {
+        match self {
+            CameraMode::Orbit => CameraMode::Chase,
+            CameraMode::Chase => CameraMode::FirstPerson,
+            CameraMode::FirstPerson => CameraMode::Spectator,
+            CameraMode::Spectator => CameraMode::Orbit,
+        }
+    }
+}
+
+impl fmt::Display for CameraMode {
+    // Writes the mode as it is named in the settings file
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            CameraMode::Orbit => "orbit",
+            CameraMode::Chase => "chase",
+            CameraMode::FirstPerson => "first_person",
+            CameraMode::Spectator => "spectator",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for CameraMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "orbit" => Ok(CameraMode::Orbit),
+            "chase" => Ok(CameraMode::Chase),
+            "first_person" => Ok(CameraMode::FirstPerson),
+            "spectator" => Ok(CameraMode::Spectator),
+            _ => Err(()),
+        }
+    }
+}
+
+// Camera controller, places the eye for one camera mode
+// Every mode shares the camera_angle (heading) and tilt of PlayerCamera, so switching modes keeps
+// looking the same way and forward keeps moving the same way
+pub trait CameraController {
+    // Method to get the eye position and the point it looks at
+    fn eye_and_center(&self, player_pos: Vec3A, camera_angle: f32, tilt: f32) -> (Vec3A, Vec3A);
+
+    // Method to get the up vector of the view
+    fn up(&self, _camera_angle: f32, _tilt: f32) -> Vec3A {
+        Vec3A::Y
+    }
+
+    // Method to get the range tilt is clamped to
+    fn tilt_range(&self) -> (f32, f32) {
+        (-LOOK_LIMIT, LOOK_LIMIT)
+    }
+
+    // Method to advance the controller by dt seconds, for smoothing that follows the player
+    fn update(&mut self, _camera_angle: &mut f32, _player_vec: Vec3A, _dt: f32) {}
+
+    // Method to zoom in by some amount, negative zooms out
+    fn zoom(&mut self, _amount: f32) {}
+
+    // Getter for a field of view overriding CameraBare::fov
+    fn fov(&self) -> Option<f32> {
+        None
+    }
+
+    // Method to check if the camera trails the player on a spring rather than sitting on it
+    fn smoothed(&self) -> bool {
+        true
+    }
+
+    // Method to check if terrain between the player and the eye should pull the eye in
+    fn occludable(&self) -> bool {
+        false
+    }
+}
+
+// Orbit settings, the camera circles the player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitCamera {
+    pub radius: f32,      // camera distance away from player
+    pub zoom_radius: f32, // radius eases towards this after zooming
+    pub min_radius: f32,  // prevents zooming in too close
+}
+
+impl CameraController for OrbitCamera {
+    fn eye_and_center(&self, player_pos: Vec3A, camera_angle: f32, tilt: f32) -> (Vec3A, Vec3A) {
+        let eye = vec3a(
+            self.radius * f32::sin(camera_angle) * f32::cos(tilt),
+            self.radius * f32::sin(tilt),
+            self.radius * f32::cos(camera_angle) * f32::cos(tilt),
+        ) + player_pos;
+        (eye, player_pos)
+    }
+
+    // tilted with the eye so looking straight down is not degenerate
+    fn up(&self, camera_angle: f32, tilt: f32) -> Vec3A {
+        Vec3A::normalize(vec3a(
+            f32::sin(camera_angle) * -f32::sin(tilt),
+            f32::cos(tilt),
+            f32::cos(camera_angle) * -f32::sin(tilt),
+        ))
+    }
+
+    // 0 to pi/2 - tilt from ground to bird's eye, never completely flat
+    fn tilt_range(&self) -> (f32, f32) {
+        (1e-6, PI / 2.0)
+    }
+
+    fn update(&mut self, _camera_angle: &mut f32, _player_vec: Vec3A, dt: f32) {
+        self.radius = ease(self.radius, self.zoom_radius, ZOOM_RATE, dt);
+    }
+
+    fn zoom(&mut self, amount: f32) {
+        self.zoom_radius = (self.zoom_radius - amount).max(self.min_radius);
+    }
+
+    fn occludable(&self) -> bool {
+        true
+    }
+}
+
+// Chase settings, the camera swings round behind the direction the player rolls
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChaseCamera {
+    pub distance: f32,
+    pub zoom_distance: f32, // distance eases towards this after zooming
+    pub height: f32,     // eye height above the player
+    pub turn_rate: f32,  // how quickly the heading follows the velocity, per second
+    pub min_speed: f32,  // slower than this (per frame) the heading is left alone
+}
+
+impl CameraController for ChaseCamera {
+    fn eye_and_center(&self, player_pos: Vec3A, camera_angle: f32, _tilt: f32) -> (Vec3A, Vec3A) {
+        let behind = vec3a(f32::sin(camera_angle), 0.0, f32::cos(camera_angle));
+        let eye = player_pos + self.distance * behind + vec3a(0.0, self.height, 0.0);
+        // look a little ahead of the player so it sits in the lower part of the screen
+        (eye, player_pos - 0.5 * self.distance * behind)
+    }
+
+    fn update(&mut self, camera_angle: &mut f32, player_vec: Vec3A, dt: f32) {
+        self.distance = ease(self.distance, self.zoom_distance, ZOOM_RATE, dt);
+        let velocity = vec3a(player_vec.x, 0.0, player_vec.z);
+        if velocity.length() < self.min_speed {
+            return;
+        }
+        // forward is (-sin, -cos) of the heading, so the heading of the velocity is
+        let target = f32::atan2(-velocity.x, -velocity.z);
+        let mut difference = (target - *camera_angle) % (2.0 * PI);
+        if difference > PI {
+            difference -= 2.0 * PI;
+        } else if difference < -PI {
+            difference += 2.0 * PI;
+        }
+        *camera_angle += difference * (1.0 - f32::exp(-self.turn_rate * dt));
+    }
+
+    fn zoom(&mut self, amount: f32) {
+        self.zoom_distance = (self.zoom_distance - amount).max(0.3);
+    }
+
+    fn occludable(&self) -> bool {
+        true
+    }
+}
+
+// First person settings, the camera looks out from the top of the sphere
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FirstPersonCamera {
+    pub eye_height: f32, // above the player centre, clear of the sphere
+    pub fov: f32,
+}
+
+impl CameraController for FirstPersonCamera {
+    fn eye_and_center(&self, player_pos: Vec3A, camera_angle: f32, tilt: f32) -> (Vec3A, Vec3A) {
+        let eye = player_pos + vec3a(0.0, self.eye_height, 0.0);
+        (eye, eye + look_direction(camera_angle, tilt))
+    }
+
+    fn fov(&self) -> Option<f32> {
+        Some(self.fov)
+    }
+
+    // a trailing eye would end up inside the sphere
+    fn smoothed(&self) -> bool {
+        false
+    }
+}
+
+// Spectator settings, the camera flies freely and ignores the player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectatorCamera {
+    pub pos: Vec3A,
+    pub speed: f32, // units per second
+}
+
+impl SpectatorCamera {
+    // Method to fly along the view, forward, right and up are -1 to 1
+    // Up is always straight up, whichever way the camera is tilted
+    pub fn fly(&mut self, camera_angle: f32, tilt: f32, forward: f32, right: f32, up: f32, dt: f32) {
+        let ahead = look_direction(camera_angle, tilt);
+        let side = ahead.cross(Vec3A::Y).normalize_or_zero();
+        self.pos += self.speed * dt * (forward * ahead + right * side + up * Vec3A::Y);
+    }
+}
+
+impl CameraController for SpectatorCamera {
+    fn eye_and_center(&self, _player_pos: Vec3A, camera_angle: f32, tilt: f32) -> (Vec3A, Vec3A) {
+        (self.pos, self.pos + look_direction(camera_angle, tilt))
+    }
+
+    fn zoom(&mut self, amount: f32) {
+        // scrolling changes how fast the spectator flies
+        self.speed = (self.speed * f32::exp(amount)).clamp(0.5, 50.0);
+    }
+}
+
+// Helper to move `current` towards `target`, closing the gap at `rate` per second
+// Exponential, so the result is the same however the time is split into frames
+fn ease(current: f32, target: f32, rate: f32, dt: f32) -> f32 {
+    target + (current - target) * f32::exp(-rate * dt)
+}
+
+// Helper to advance a critically damped spring towards `target` over dt seconds
+// `smooth_time` is roughly how long the spring takes to catch up, the integration is exact for
+// any dt so following doesn't depend on the frame rate
+fn spring(current: Vec3A, velocity: &mut Vec3A, target: Vec3A, smooth_time: f32, dt: f32) -> Vec3A {
+    let omega = 2.0 / smooth_time.max(1e-4);
+    let x = omega * dt;
+    let decay = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (*velocity + omega * change) * dt;
+    *velocity = (*velocity - omega * temp) * decay;
+    target + (change + temp) * decay
+}
+
+// Helper to get the unit direction looked along at a heading, tilt above zero looks down
+fn look_direction(camera_angle: f32, tilt: f32) -> Vec3A {
+    vec3a(
+        -f32::sin(camera_angle) * f32::cos(tilt),
+        -f32::sin(tilt),
+        -f32::cos(camera_angle) * f32::cos(tilt),
+    )
+}
+
 // Camera
 pub struct PlayerCamera {
     pub player_pos: Vec3A, //players coords
+    pub focus: Vec3A,      // player_pos trailed on a spring, what the camera follows
+    focus_velocity: Vec3A,
+    pub follow_time: f32,  // seconds the spring takes to catch up with the player
+    occlusion: f32,        // fraction of the eye distance kept clear of terrain, 1.0 if nothing is in the way
     pub camera_angle: f32, // 0 to 2pi, 0 is behind player
-    pub tilt: f32,         // 0 to pi - tilt from ground to bird's eye
-    pub radius: f32,       // camera distance away from player
+    pub tilt: f32,         // orbit: angle of the eye above the ground, others: angle looked down
+    pub mode: CameraMode,
+    pub orbit: OrbitCamera,
+    pub chase: ChaseCamera,
+    pub first_person: FirstPersonCamera,
+    pub spectator: SpectatorCamera,
     pub camera_bare: CameraBare,
 }
 
@@ -40,9 +315,33 @@ impl PlayerCamera {
     pub fn new(player_pos: Vec3A, aspect: f32, camera_angle: f32) -> Self {
         PlayerCamera {
             player_pos,
+            focus: player_pos,
+            focus_velocity: Vec3A::ZERO,
+            follow_time: 0.08,
+            occlusion: 1.0,
             camera_angle,
             tilt: 0.6,
-            radius: 2.0,
+            mode: CameraMode::Orbit,
+            orbit: OrbitCamera {
+                radius: 2.0,
+                zoom_radius: 2.0,
+                min_radius: 0.1,
+            },
+            chase: ChaseCamera {
+                distance: 1.5,
+                zoom_distance: 1.5,
+                height: 0.6,
+                turn_rate: 3.0,
+                min_speed: 0.002,
+            },
+            first_person: FirstPersonCamera {
+                eye_height: 0.15,
+                fov: PI / 2.5,
+            },
+            spectator: SpectatorCamera {
+                pos: player_pos + vec3a(0.0, 3.0, 0.0),
+                speed: 4.0,
+            },
             camera_bare: CameraBare {
                 fov: PI / 3.0,
                 aspect,
@@ -51,119 +350,148 @@ impl PlayerCamera {
             },
         }
     }
-    // helper function to update camera to 'new' sphere when respawn
-    pub fn update(
-        player_pos: Vec3A,
-        aspect: f32,
-        camera_angle: f32,
-        old_cam: PlayerCamera,
-    ) -> Self {
-        PlayerCamera {
-            player_pos,
-            camera_angle,
-            tilt: old_cam.tilt,
-            radius: old_cam.radius,
-            camera_bare: CameraBare {
-                fov: old_cam.camera_bare.fov,
-                aspect,
-                near: old_cam.camera_bare.near,
-                far: old_cam.camera_bare.far,
-            },
+
+    // Method to apply the camera settings from the settings file
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.set_mode(settings.camera_mode);
+        self.orbit.radius = settings.orbit_radius;
+        self.orbit.zoom_radius = settings.orbit_radius;
+        self.chase.distance = settings.chase_distance;
+        self.chase.zoom_distance = settings.chase_distance;
+        self.chase.height = settings.chase_height;
+        self.first_person.fov = settings.first_person_fov.to_radians();
+        self.spectator.speed = settings.spectator_speed;
+    }
+
+    // Method to move the camera to a respawned player, keeping every mode's settings
+    // The spring is reset so the camera cuts to the new position instead of flying there
+    pub fn respawn(&mut self, player_pos: Vec3A, camera_angle: f32) {
+        self.player_pos = player_pos;
+        self.focus = player_pos;
+        self.focus_velocity = Vec3A::ZERO;
+        self.occlusion = 1.0;
+        self.camera_angle = camera_angle;
+    }
+
+    // Method to switch camera mode
+    // The spectator starts from wherever the eye was so the view doesn't jump
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Spectator && self.mode != CameraMode::Spectator {
+            self.spectator.pos = self.eye();
         }
+        self.mode = mode;
     }
 
-    pub fn detect_col(&self, other: &Entity) -> (bool, f32) {
-        // Performing collision detection logic
-        for face in &other.mesh.faces {
-            let a = other.mesh.vertices[face.vertices[0]] + other.pos;
-            let b = other.mesh.vertices[face.vertices[1]] + other.pos;
-            let c = other.mesh.vertices[face.vertices[2]] + other.pos;
-            let face_normal = vec3a(face.normal[0], face.normal[1], face.normal[2]).normalize();
-
-            let d = (a - self.eye()).dot(face_normal);
-
-            // Check if plane intersects sphere
-            if d <= 0.0 {
-                let p = self.eye() - d * face_normal;
-                let n = (b - a).cross(c - a);
-                let area = n.length() * 0.5;
-
-                // Barycentric check if sphere-plane intersection point is in triangle
-                let f = |x: Vec3A, y: Vec3A, z: Vec3A| -> f32 {
-                    let ng = (y - x).cross(z - x);
-                    let m = ng.dot(n);
-                    if m >= 0.0 {
-                        return 0.5 * ng.length() / area;
-                    } else {
-                        return -0.5 * ng.length() / area;
-                    }
-                };
-
-                let alpha = f(p, b, c);
-                let beta = f(a, p, c);
-                let gamma = f(a, b, p);
-
-                // Closure to check if a f32 is in between 0.0 and 1.0
-                let inrange = |x| (x >= 0.0 && x <= 1.0); 
-                if inrange(alpha) && inrange(beta) && inrange(gamma) {
-                    return (true, -d);
-                }
-            }
+    // Getter for the controller of the current mode
+    pub fn controller(&self) -> &dyn CameraController {
+        match self.mode {
+            CameraMode::Orbit => &self.orbit,
+            CameraMode::Chase => &self.chase,
+            CameraMode::FirstPerson => &self.first_person,
+            CameraMode::Spectator => &self.spectator,
         }
+    }
 
-        (false, 0.0)
+    // Mutable getter for the controller of the current mode
+    pub fn controller_mut(&mut self) -> &mut dyn CameraController {
+        match self.mode {
+            CameraMode::Orbit => &mut self.orbit,
+            CameraMode::Chase => &mut self.chase,
+            CameraMode::FirstPerson => &mut self.first_person,
+            CameraMode::Spectator => &mut self.spectator,
+        }
     }
 
-    pub fn collide(&mut self, ground: &Entity) {
-        let (collide_ground, amt) = self.detect_col(ground);
-        if collide_ground {
-            let hypotenuse = (self.player_pos - self.eye()).length();
-            self.tilt += f32::sin(amt/hypotenuse);
+    // Method to advance the follow spring and the current controller by dt seconds
+    // `player_vec` is the player velocity
+    pub fn update(&mut self, player_vec: Vec3A, dt: f32) {
+        self.focus = if self.controller().smoothed() {
+            spring(self.focus, &mut self.focus_velocity, self.player_pos, self.follow_time, dt)
+        } else {
+            self.focus_velocity = Vec3A::ZERO;
+            self.player_pos
+        };
+        let mut camera_angle = self.camera_angle;
+        self.controller_mut().update(&mut camera_angle, player_vec, dt);
+        self.camera_angle = camera_angle;
+    }
+
+    // Method to zoom the current controller in by some amount, negative zooms out
+    pub fn zoom(&mut self, amount: f32) {
+        self.controller_mut().zoom(amount);
+    }
+
+    // Method to pull the eye in front of anything in `occluders` between it and the player
+    // The eye moves in at once when something blocks the view, and eases back out once it clears
+    pub fn occlude(&mut self, occluders: &[&Entity], dt: f32) {
+        if !self.controller().occludable() {
+            self.occlusion = 1.0;
+            return;
         }
+        self.clamp_angles();
+        let (eye, _) = self.controller().eye_and_center(self.focus, self.camera_angle, self.tilt);
+        let length = eye.distance(self.focus).max(f32::EPSILON);
+        let clear = occluders
+            .iter()
+            .filter_map(|e| e.raycast(self.focus, eye))
+            .map(|t| ((t * length - OCCLUSION_MARGIN) / length).max(MIN_OCCLUSION))
+            .fold(1.0, f32::min);
+        self.occlusion = if clear < self.occlusion {
+            clear
+        } else {
+            ease(self.occlusion, clear, OCCLUSION_RELEASE_RATE, dt)
+        };
     }
 
     //main function to get view matrix
     pub fn view_mat(&mut self) -> Mat4 {
-        if self.radius < 0.1 {
-            self.radius = 0.1 //prevents zooming in too close
-        }
+        self.clamp_angles();
+        let (eye, center) = self.eye_and_center();
+        Mat4::look_at_rh(eye.into(), center.into(), self.up().into())
+    }
+
+    //helper function to keep camera_angle within 0 to 2pi and tilt within the mode's range
+    fn clamp_angles(&mut self) {
         if self.camera_angle < 0.0 {
             self.camera_angle += 2.0 * PI; // allows for camera to spin horinzontaly constantly around player while preventing int underflow
         }
         else if self.camera_angle > 2.0 * PI{
             self.camera_angle -= 2.0 * PI; // allows for camera to spin horinzontaly constantly around player while preventing int overflow
         }
-        self.camera_angle = self.camera_angle % (2.0 * PI);
+        self.camera_angle %= 2.0 * PI;
 
-        if self.tilt > PI / 2.0 {
-            self.tilt = PI / 2.0; // clip max tilt to 90deg
-        } else if self.tilt < 1e-6 {
-            self.tilt = 1e-6; // prevent edge case of completely flat camera
-        }
+        let (min_tilt, max_tilt) = self.controller().tilt_range();
+        self.tilt = self.tilt.clamp(min_tilt, max_tilt);
+    }
 
-        Mat4::look_at_rh(self.eye().into(), self.player_pos.into(), self.up().into())
+    //helper function to get eye and look at vectors, with the eye pulled in if occluded
+    fn eye_and_center(&self) -> (Vec3A, Vec3A) {
+        let (eye, center) = self.controller().eye_and_center(self.focus, self.camera_angle, self.tilt);
+        (self.focus + self.occlusion * (eye - self.focus), center)
     }
 
     //helper function to get eye vector
     pub fn eye(&self) -> Vec3A {
-        vec3a(
-            self.radius * f32::sin(self.camera_angle) * f32::cos(self.tilt),
-            self.radius * f32::sin(self.tilt),
-            self.radius * f32::cos(self.camera_angle) * f32::cos(self.tilt),
-        ) + self.player_pos
+        self.eye_and_center().0
+    }
+
+    //helper function to get the vector to the right of the view, for placing the listener's ears
+    pub fn right(&self) -> Vec3A {
+        let (eye, center) = self.eye_and_center();
+        (center - eye).cross(self.up()).normalize_or_zero()
     }
 
     //helper function to get up vector
     pub fn up(&self) -> Vec3A {
-        Vec3A::normalize(vec3a(
-            self.radius * f32::sin(self.camera_angle) * -f32::sin(self.tilt),
-            self.radius * f32::cos(self.tilt),
-            self.radius * f32::cos(self.camera_angle) * -f32::sin(self.tilt),
-        ))
+        self.controller().up(self.camera_angle, self.tilt)
     }
 
     //gets projection matrix
     pub fn proj_mat(&mut self) -> Mat4 {
-        self.camera_bare.proj_mat()
+        let mut bare = self.camera_bare.clone();
+        if let Some(fov) = self.controller().fov() {
+            bare.fov = fov;
+        }
+        bare.proj_mat()
     }
 }
@@ -1,10 +1,15 @@
 extern crate gl;
 extern crate glam;
+use crate::assets::{AssetError, Assets, TextureHandle};
+use crate::bounds::BoundingSphere;
 use crate::camera::PlayerCamera;
-use crate::meshloader::Mesh;
-use crate::shader::ShaderProgram;
-use gl::types::{GLint, GLuint};
+use crate::meshloader::{Mesh, UvMode};
+use crate::renderer::{Backend, DrawItem, Material, ProgramHandle, Renderer};
 use glam::*;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const DEFAULT_TEXTURE: &str = "white";
 
 // Player abilities (to be expanded in future)
 pub struct Ability {
@@ -27,15 +32,16 @@ impl Player {
 
     // Player constructor
     pub fn new(
-        stl_path: &str,
+        assets: &mut Assets,
+        mesh_name: &str,
         pos: Vec3A,
         color: Vec3A,
         camera: PlayerCamera,
         bounce: f32,
         player_id: u8,
-    ) -> Self {
-        let e = Entity::new(stl_path, pos, color, bounce);
-        Player {
+    ) -> Result<Self, AssetError> {
+        let e = Entity::new(assets, mesh_name, pos, color, bounce)?;
+        Ok(Player {
             vec: vec3a(0.0, 0.0, 0.0),
             entity: e,
             camera,
@@ -45,7 +51,7 @@ impl Player {
                 ground_mut_power: 0.08,
             },
             player_id,
-        }
+        })
     }
 
     // Method to move player
@@ -94,10 +100,10 @@ impl Player {
     pub fn detect_col(&self, other: &Entity) -> (bool, Vec3A, f32) {
         // Performing collision detection logic
         for face in &other.mesh.faces {
-            let a = other.mesh.vertices[face.vertices[0]] + other.pos;
-            let b = other.mesh.vertices[face.vertices[1]] + other.pos;
-            let c = other.mesh.vertices[face.vertices[2]] + other.pos;
-            let face_normal = vec3a(face.normal[0], face.normal[1], face.normal[2]).normalize();
+            let a = other.world_vertex(face.vertices[0]);
+            let b = other.world_vertex(face.vertices[1]);
+            let c = other.world_vertex(face.vertices[2]);
+            let face_normal = other.world_normal(face.normal);
 
             let d = (self.entity.pos - a).project_onto(face_normal).length();
 
@@ -112,9 +118,9 @@ impl Player {
                     let ng = (y - x).cross(z - x);
                     let m = ng.dot(n);
                     if m >= 0.0 {
-                        return 0.5 * ng.length() / area;
+                        0.5 * ng.length() / area
                     } else {
-                        return -0.5 * ng.length() / area;
+                        -0.5 * ng.length() / area
                     }
                 };
 
@@ -123,7 +129,7 @@ impl Player {
                 let gamma = f(a, b, p);
 
                 // Closure to check if a f32 is in between 0.0 and 1.0
-                let inrange = |x| (x >= 0.0 && x <= 1.0); 
+                let inrange = |x: f32| (0.0..=1.0).contains(&x);
                 if inrange(alpha) && inrange(beta) && inrange(gamma) {
                     return (true, face_normal, self.radius - d);
                 }
@@ -167,42 +173,85 @@ impl Player {
 
 
 // Entity struct for all entities in game
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Entity {
-    pub mesh: Mesh,
-    pub pos: Vec3A,
-    pub vao: u32,
-    pub vbo: u32,
-    pub color: Vec3A,
-    pub reflectance: f32,
+    pub mesh: Rc<Mesh>, // shared with the asset cache until mutated
+    pub pos: Vec3A,      // translation
+    pub rotation: Quat,
+    pub scale: Vec3A,    // non-uniform scale, applied before rotation
+    pub material: Material,
     pub bounce: f32,
-    pub scale: Vec3A,
-    pub texture: GLuint,
-    pub texture_id: i8,
+    pub texture_name: Option<String>, // None uses the mesh material's texture, else DEFAULT_TEXTURE
 }
 
 // Entity methods
 impl Entity {
-    // Entity constructor
-    pub fn new(stl_path: &str, pos: Vec3A, color: Vec3A, bounce: f32) -> Self {
+    // Entity constructor, mesh_name is relative to the asset root (e.g. "mesh/ground.stl")
+    pub fn new(
+        assets: &mut Assets,
+        mesh_name: &str,
+        pos: Vec3A,
+        color: Vec3A,
+        bounce: f32,
+    ) -> Result<Self, AssetError> {
         let scale = vec3a(1.0, 1.0, 1.0);
-        let m = Mesh::new(stl_path, scale);
-        Entity {
+        let m = assets.mesh(mesh_name)?;
+        Ok(Entity {
             mesh: m,
             pos,
-            vao: 0,
-            vbo: 0,
-            color,
-            reflectance: 1.0,
-            bounce,
+            rotation: Quat::IDENTITY,
             scale,
-            texture:0,
-            texture_id:0,
+            material: Material::new(color),
+            bounce,
+            texture_name: None,
+        })
+    }
+    // Setter for entity texture, by name of a file in assets/textures (extension optional)
+    pub fn set_texture(&mut self, name: &str) {
+        self.texture_name = Some(String::from(name));
+    }
+
+    // Setter for the entity mesh, keeping the current texture coordinate projection
+    pub fn set_mesh(&mut self, mesh: Rc<Mesh>) {
+        let uv_mode = self.mesh.uv_mode;
+        self.mesh = mesh;
+        self.set_uv_mode(uv_mode);
+    }
+
+    // Method to swap in a hot reloaded mesh if this entity's mesh file is in changed
+    // A deformed mesh is kept, as its edits came from the server and are shared with every client
+    pub fn reload_mesh(&mut self, assets: &Assets, changed: &[PathBuf]) {
+        let path = Path::new(&self.mesh.path);
+        if changed.iter().any(|c| c == path) {
+            if self.mesh.deformed() {
+                println!("Keeping deformed {} until it is reset", path.display());
+                return;
+            }
+            if let Some(mesh) = assets.cached_mesh(path) {
+                self.set_mesh(mesh);
+            }
         }
     }
-    // Setter for entity texture
-    pub fn set_texture_id(&mut self, new_texture_id:i8){
-        self.texture_id = new_texture_id
+
+    // Setter for the texture coordinate projection of the entity's mesh
+    // The shared mesh is only copied if the projection actually changes
+    pub fn set_uv_mode(&mut self, uv_mode: UvMode) {
+        if self.mesh.uv_mode != uv_mode {
+            Rc::make_mut(&mut self.mesh).set_uv_mode(uv_mode);
+        }
+    }
+
+    /// Method to look up the texture used by this entity
+    /// Order: explicit texture name, texture referenced by the mesh material, DEFAULT_TEXTURE
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn load_texture(&self, assets: &mut Assets) -> Result<TextureHandle, AssetError> {
+        match (&self.texture_name, &self.mesh.material_texture) {
+            (Some(name), _) => assets.texture(name),
+            (None, Some(material_texture)) => assets.texture_file(Path::new(material_texture)),
+            (None, None) => assets.texture(DEFAULT_TEXTURE),
+        }
     }
 
     // Setter for entity position
@@ -210,11 +259,92 @@ impl Entity {
         self.pos = new_pos
     }
     
-    // Method to scale the mesh of an entity
+    // Setter for entity scale, applied through the model matrix
     pub fn set_scale(&mut self, x: f32, y: f32, z: f32) {
         self.scale = vec3a(x, y, z);
-        let m = Mesh::new(&self.mesh.path as &str, self.scale);
-        self.mesh = m;
+    }
+
+    // Setter for entity rotation about the y-axis, used for animated spinning entities
+    pub fn set_rotation_y(&mut self, theta: f32) {
+        self.rotation = Quat::from_rotation_y(theta);
+    }
+
+    // Method to get the model matrix (scale, then rotation, then translation)
+    pub fn model_mat(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale.into(), self.rotation, self.pos.into())
+    }
+
+    // Method to get a mesh vertex in world space
+    pub fn world_vertex(&self, idx: usize) -> Vec3A {
+        self.pos + self.rotation * (self.scale * self.mesh.vertices[idx])
+    }
+
+    // Method to transform a mesh face normal into world space
+    // Normals are divided by the scale so they stay perpendicular under non-uniform scaling
+    pub fn world_normal(&self, normal: Vec3A) -> Vec3A {
+        (self.rotation * (normal / self.scale)).normalize_or_zero()
+    }
+
+    // Method to convert a world space direction into the mesh's object space
+    pub fn local_dir(&self, dir: Vec3A) -> Vec3A {
+        (self.rotation.inverse() * dir) / self.scale
+    }
+
+    // Method to get the world space bounding sphere of the entity, used for culling
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        self.mesh.bounds.transformed(&self.model_mat())
+    }
+
+    // Method to find where a world space segment first crosses the mesh
+    // Returns the fraction of the way from `from` to `to`, or None if nothing is hit. The segment is
+    // moved into object space rather than transforming every vertex.
+    pub fn raycast(&self, from: Vec3A, to: Vec3A) -> Option<f32> {
+        let bounds = self.bounding_sphere();
+        let closest = (bounds.center - from).dot(to - from) / (to - from).length_squared().max(f32::EPSILON);
+        let nearest = from + closest.clamp(0.0, 1.0) * (to - from);
+        if nearest.distance(bounds.center) > bounds.radius {
+            return None;
+        }
+
+        let inverse = self.model_mat().inverse();
+        let origin = inverse.transform_point3a(from);
+        let dir = inverse.transform_point3a(to) - origin;
+        let mut hit: Option<f32> = None;
+        for face in &self.mesh.faces {
+            let [a, b, c] = face.vertices.map(|v| self.mesh.vertices[v]);
+            // Moller-Trumbore, both sides of the face count
+            let (e1, e2) = (b - a, c - a);
+            let p = dir.cross(e2);
+            let det = e1.dot(p);
+            if det.abs() < 1e-9 {
+                continue;
+            }
+            let s = (origin - a) / det;
+            let u = s.dot(p);
+            if !(0.0..=1.0).contains(&u) {
+                continue;
+            }
+            let q = s.cross(e1);
+            let v = dir.dot(q);
+            if v < 0.0 || u + v > 1.0 {
+                continue;
+            }
+            let t = e2.dot(q);
+            let closer = match hit {
+                Some(h) => t < h,
+                None => true,
+            };
+            if (0.0..=1.0).contains(&t) && closer {
+                hit = Some(t);
+            }
+        }
+        hit
+    }
+
+    // Method to mutate a single mesh vertex by a world space direction and amount
+    pub fn mutate(&mut self, idx: usize, dir: Vec3A, amount: f32) {
+        let local_dir = self.local_dir(dir);
+        Rc::make_mut(&mut self.mesh).mutate(idx, local_dir, amount);
     }
 
     // Method to move an entity
@@ -224,132 +354,42 @@ impl Entity {
 
     // Setter for entity color
     pub fn set_color(&mut self, color: Vec3A) {
-        self.color = color;
+        self.material.color = color;
     }
 
-    // Method to initialize entity VAO and VBO, as well as loading the textures
-    pub unsafe fn gl_init(&mut self) {
-        gl::GenVertexArrays(1, &mut self.vao);
-        assert_ne!(self.vao, 0);
-        gl::GenBuffers(1, &mut self.vbo);
-        assert_ne!(self.vbo, 0);
-
-        // textures
-        gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_WRAP_S,
-            gl::MIRRORED_REPEAT as GLint,
-        );
-        gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_WRAP_T,
-            gl::MIRRORED_REPEAT as GLint,
-        );
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
-        gl::ActiveTexture(gl::TEXTURE0);
-        dbg!(&self.texture_id.to_string());
-        let data = image::open("assets/textures/".to_owned()+ &self.texture_id.to_string()+".jpg")
-            .expect("Failed to load texture image")
-            .flipv()
-            .to_rgb8();
-        let mut texture = 0;
-        let (width, height) = data.dimensions();
-        gl::GenTextures(1, &mut texture);
-        gl::BindTexture(gl::TEXTURE_2D, texture);
-        gl::TexImage2D(
-            gl::TEXTURE_2D,
-            0,
-            gl::RGB as i32,
-            width as GLint,
-            height as GLint,
-            0,
-            gl::RGB,
-            gl::UNSIGNED_BYTE,
-            data.as_ptr() as *const _,
-        );
-        gl::GenerateMipmap(gl::TEXTURE_2D);
-        self.texture = texture;
+    /// Method to load the entity's texture, GPU buffers for the mesh are owned by the renderer
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn gl_init(&mut self, assets: &mut Assets) -> Result<(), AssetError> {
+        self.material.texture = self.load_texture(assets)?;
+        Ok(())
     }
 
-    // Method to draw the entity on some player camera using a specified shader
-    pub unsafe fn draw(&mut self,
-                       camera: &mut PlayerCamera,
-                       lighting_program: &ShaderProgram,
-                       ) {
-
-        let vertices = self.mesh.vertices_flattened();
-        gl::BindVertexArray(self.vao);
-        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            (vertices.len() * std::mem::size_of::<f32>()) as isize,
-            vertices.as_ptr().cast(),
-            gl::STATIC_DRAW,
-        );
-        gl::VertexAttribPointer(
-            0,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            (9 * std::mem::size_of::<f32>()).try_into().unwrap(),
-            0 as *const _,
-        );
-        gl::EnableVertexAttribArray(0);
-        gl::VertexAttribPointer(
-            1,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            (9 * std::mem::size_of::<f32>()).try_into().unwrap(),
-            (3 * std::mem::size_of::<f32>()) as *const _,
-        );
-        gl::EnableVertexAttribArray(1);
-        gl::VertexAttribPointer(
-            2,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            (9 * std::mem::size_of::<f32>()).try_into().unwrap(),
-            (6 * std::mem::size_of::<f32>()) as *const _,
-        );
-        gl::EnableVertexAttribArray(2);
-
-        let t_mat = Mat4::from_translation(self.pos.into());
-        lighting_program.set_mat4f(b"proj\0", &camera.proj_mat().to_cols_array()[0]);
-        lighting_program.set_mat4f(b"view\0", &camera.view_mat().to_cols_array()[0]);
-        lighting_program.set_mat4f(b"model\0", &t_mat.to_cols_array()[0]);
-        let object_colour = self.color * self.reflectance;
-        lighting_program.set_vec3f(
-            b"objectColor\0",
-            object_colour[0],
-            object_colour[1],
-            object_colour[2],
-        );
-
-        gl::BindTexture(gl::TEXTURE_2D, self.texture);
-        gl::BindVertexArray(self.vao);
-        gl::Uniform1i(self.texture_id as GLint, 0);
-        gl::DrawArrays(gl::TRIANGLES, 0, self.mesh.vertices_normals_tex.len() as i32);
+    // Method to build the draw item for this entity, uploading its mesh on first use
+    pub fn draw_item<B: Backend>(&self, renderer: &mut Renderer<B>, program: ProgramHandle) -> DrawItem {
+        DrawItem {
+            mesh: renderer.mesh(&self.mesh),
+            program,
+            material: self.material,
+            transform: self.model_mat(),
+            bounds: Some(self.bounding_sphere()),
+        }
     }
 
     // Method to retrieve the closest vertex index at location (x, 0.0, z)
     // Used for ground mutation based on raycasted mouse coordinates
     pub fn closest_vertex_index(&mut self, xz: Vec2) -> usize {
-        let m = &mut self.mesh;
         let mut min_d = f32::MAX;
 
-        let mut closest_idx = 0 as usize;
+        let mut closest_idx: usize = 0;
 
-        let mut i = 0;
-        for v in &m.vertices {
-            let d = v.distance(vec3a(xz.x, 0.0, xz.y));
+        for i in 0..self.mesh.vertices.len() {
+            let d = self.world_vertex(i).distance(vec3a(xz.x, 0.0, xz.y));
             if d < min_d {
                 min_d = d;
                 closest_idx = i;
             }
-            i += 1;
         }
         closest_idx
     }
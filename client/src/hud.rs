@@ -0,0 +1,348 @@
+use crate::shader::ShaderProgram;
+use glam::{vec2, Mat4, Vec2, Vec3A, Vec4};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Bitmap font, 5x7 pixel glyphs for ASCII ' ' to '_', lowercase is drawn as uppercase
+// Each glyph is seven rows from the top, the low five bits of a row are its pixels left to right
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const FIRST_GLYPH: char = ' ';
+const GLYPHS: [[u8; GLYPH_HEIGHT]; 66] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // !
+    [0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A], // #
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04], // $
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // %
+    [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D], // &
+    [0x04, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // (
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // )
+    [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00], // *
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08], // ,
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C], // .
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // /
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E], // 0
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E], // 1
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F], // 2
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E], // 3
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02], // 4
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E], // 5
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E], // 6
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // 7
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E], // 8
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C], // 9
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00], // :
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08], // ;
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // <
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00], // =
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // >
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // ?
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E], // @
+    [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11], // A
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E], // B
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E], // C
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C], // D
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F], // E
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10], // F
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F], // G
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // H
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // I
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C], // J
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // K
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F], // L
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11], // M
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // N
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // O
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10], // P
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D], // Q
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11], // R
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E], // S
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // T
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // U
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04], // V
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A], // W
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11], // X
+    [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04], // Y
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F], // Z
+    [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E], // [
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // \
+    [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E], // ]
+    [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F], // _
+    [0x00, 0x0A, 0x1F, 0x1F, 0x0E, 0x04, 0x00], // heart, drawn for HEART
+    [0x1F, 0x1F, 0x1F, 0x1F, 0x1F, 0x1F, 0x1F], // solid block, used for rect and triangle
+];
+pub const HEART: char = '\u{2665}';
+const HEART_GLYPH: usize = 64;
+const SOLID_GLYPH: usize = 65;
+// each glyph gets a cell with a one pixel empty border right and below so scaled glyphs don't bleed
+const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+const CELL_HEIGHT: usize = GLYPH_HEIGHT + 1;
+const FLOATS_PER_VERTEX: usize = 8; // position xy, texture uv, colour rgba
+
+const KILL_FEED_LENGTH: usize = 5;
+const KILL_FEED_TIMEOUT: Duration = Duration::from_secs(6);
+
+// 2D overlay drawn in screen pixels over the 3D scene, origin at the top left
+// Quads are batched between begin and draw, so a whole HUD is one draw call
+pub struct Hud {
+    pub program: ShaderProgram,
+    font: u32,
+    vao: u32,
+    vbo: u32,
+    screen: Vec2,
+    vertices: Vec<f32>,
+}
+
+// Hud methods
+impl Hud {
+
+    /// Hud constructor, uploading the font atlas
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn new(program: ShaderProgram) -> Self {
+        let (width, height) = (GLYPHS.len() * CELL_WIDTH, CELL_HEIGHT);
+        let mut atlas = vec![0u8; width * height];
+        for (g, glyph) in GLYPHS.iter().enumerate() {
+            for (row, bits) in glyph.iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0 {
+                        atlas[row * width + g * CELL_WIDTH + col] = 255;
+                    }
+                }
+            }
+        }
+
+        let mut font = 0;
+        gl::GenTextures(1, &mut font);
+        gl::BindTexture(gl::TEXTURE_2D, font);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::R8 as i32,
+            width as i32,
+            height as i32,
+            0,
+            gl::RED,
+            gl::UNSIGNED_BYTE,
+            atlas.as_ptr().cast(),
+        );
+        // nearest filtering keeps the pixel font crisp at any integer scale
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+
+        let (mut vao, mut vbo) = (0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindVertexArray(vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        let stride = (FLOATS_PER_VERTEX * std::mem::size_of::<f32>()) as i32;
+        for (attrib, size, offset) in [(0, 2, 0), (1, 2, 2), (2, 4, 4)] {
+            gl::VertexAttribPointer(
+                attrib,
+                size,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                (offset * std::mem::size_of::<f32>()) as *const _,
+            );
+            gl::EnableVertexAttribArray(attrib);
+        }
+        gl::BindVertexArray(0);
+
+        Hud {
+            program,
+            font,
+            vao,
+            vbo,
+            screen: vec2(1.0, 1.0),
+            vertices: vec!(),
+        }
+    }
+
+    // Method to start a new frame of HUD quads for a screen of the given size
+    pub fn begin(&mut self, width: i32, height: i32) {
+        self.screen = vec2(width.max(1) as f32, height.max(1) as f32);
+        self.vertices.clear();
+    }
+
+    // Getter for the screen size given to begin
+    pub fn screen(&self) -> Vec2 {
+        self.screen
+    }
+
+    // Method to add a solid rectangle, `pos` is the top left corner
+    pub fn rect(&mut self, pos: Vec2, size: Vec2, color: Vec4) {
+        self.quad(pos, size, SOLID_GLYPH, color);
+    }
+
+    // Method to add a solid triangle
+    pub fn triangle(&mut self, corners: [Vec2; 3], color: Vec4) {
+        // any texel inside the solid glyph will do
+        let u = (SOLID_GLYPH * CELL_WIDTH) as f32 + 0.5 * GLYPH_WIDTH as f32;
+        let u = u / (GLYPHS.len() * CELL_WIDTH) as f32;
+        let v = 0.5 * GLYPH_HEIGHT as f32 / CELL_HEIGHT as f32;
+        for corner in corners {
+            self.vertices.extend_from_slice(&[corner.x, corner.y, u, v]);
+            self.vertices.extend_from_slice(&color.to_array());
+        }
+    }
+
+    // Method to add a line of text with a drop shadow, `pos` is the top left corner
+    // `scale` is the size of one font pixel in screen pixels
+    pub fn text(&mut self, text: &str, pos: Vec2, scale: f32, color: Vec4) {
+        let shadow = Vec4::new(0.0, 0.0, 0.0, 0.6 * color.w);
+        self.glyphs(text, pos + Vec2::splat(scale), scale, shadow);
+        self.glyphs(text, pos, scale, color);
+    }
+
+    // Method to add a line of text centred horizontally on `pos.x`
+    pub fn text_centered(&mut self, text: &str, pos: Vec2, scale: f32, color: Vec4) {
+        let width = text_width(text, scale);
+        self.text(text, pos - vec2(0.5 * width, 0.0), scale, color);
+    }
+
+    // Method to add a line of text ending at `pos.x`
+    pub fn text_right(&mut self, text: &str, pos: Vec2, scale: f32, color: Vec4) {
+        let width = text_width(text, scale);
+        self.text(text, pos - vec2(width, 0.0), scale, color);
+    }
+
+    // Helper to add the glyph quads of a line of text
+    fn glyphs(&mut self, text: &str, pos: Vec2, scale: f32, color: Vec4) {
+        let size = scale * vec2(GLYPH_WIDTH as f32, GLYPH_HEIGHT as f32);
+        for (i, c) in text.chars().enumerate() {
+            if c == ' ' {
+                continue;
+            }
+            let at = pos + vec2(i as f32 * CELL_WIDTH as f32 * scale, 0.0);
+            self.quad(at, size, glyph_index(c), color);
+        }
+    }
+
+    // Helper to add a quad textured with one glyph of the font atlas
+    fn quad(&mut self, pos: Vec2, size: Vec2, glyph: usize, color: Vec4) {
+        let atlas_width = (GLYPHS.len() * CELL_WIDTH) as f32;
+        let u0 = (glyph * CELL_WIDTH) as f32 / atlas_width;
+        let u1 = (glyph * CELL_WIDTH + GLYPH_WIDTH) as f32 / atlas_width;
+        let v1 = GLYPH_HEIGHT as f32 / CELL_HEIGHT as f32;
+        let (x0, y0) = (pos.x, pos.y);
+        let (x1, y1) = (pos.x + size.x, pos.y + size.y);
+        for (x, y, u, v) in [
+            (x0, y0, u0, 0.0),
+            (x1, y0, u1, 0.0),
+            (x1, y1, u1, v1),
+            (x0, y0, u0, 0.0),
+            (x1, y1, u1, v1),
+            (x0, y1, u0, v1),
+        ] {
+            self.vertices.extend_from_slice(&[x, y, u, v]);
+            self.vertices.extend_from_slice(&color.to_array());
+        }
+    }
+
+    /// Method to draw everything added since begin over the current frame
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn draw(&mut self) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+        self.program.use_program();
+        gl::Uniform2f(self.program.location("screenSize"), self.screen.x, self.screen.y);
+        self.program.set_sampler("font", 0);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.font);
+
+        gl::BindVertexArray(self.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            std::mem::size_of_val(self.vertices.as_slice()) as isize,
+            self.vertices.as_ptr().cast(),
+            gl::STREAM_DRAW,
+        );
+        gl::DrawArrays(gl::TRIANGLES, 0, (self.vertices.len() / FLOATS_PER_VERTEX) as i32);
+        gl::BindVertexArray(0);
+
+        gl::Disable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
+
+// Helper to get the width in screen pixels of a line of text
+pub fn text_width(text: &str, scale: f32) -> f32 {
+    let count = text.chars().count();
+    if count == 0 {
+        return 0.0;
+    }
+    // no trailing gap after the last glyph
+    (count * CELL_WIDTH - 1) as f32 * scale
+}
+
+// Helper to get the atlas glyph for a character, unknown characters draw as '?'
+fn glyph_index(c: char) -> usize {
+    let c = c.to_ascii_uppercase();
+    match c {
+        HEART => HEART_GLYPH,
+        ' '..='_' => c as usize - FIRST_GLYPH as usize,
+        _ => '?' as usize - FIRST_GLYPH as usize,
+    }
+}
+
+// Helper to project a world position to HUD screen pixels
+// Returns None for points behind the camera or outside the screen
+pub fn world_to_screen(view_proj: Mat4, pos: Vec3A, screen: Vec2) -> Option<Vec2> {
+    let clip = view_proj * pos.extend(1.0);
+    if clip.w <= 0.0 {
+        return None;
+    }
+    let ndc = clip.truncate() / clip.w;
+    if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 {
+        return None;
+    }
+    Some(vec2(0.5 * (ndc.x + 1.0) * screen.x, 0.5 * (1.0 - ndc.y) * screen.y))
+}
+
+// Recent match events shown in a corner of the HUD, each fades out after KILL_FEED_TIMEOUT
+#[derive(Debug, Default)]
+pub struct KillFeed {
+    entries: VecDeque<(String, Vec4, Instant)>,
+}
+
+// KillFeed methods
+impl KillFeed {
+
+    // Method to add an event, dropping the oldest when the feed is full
+    pub fn push(&mut self, message: String, color: Vec4) {
+        if self.entries.len() == KILL_FEED_LENGTH {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((message, color, Instant::now()));
+    }
+
+    // Method to add the feed to the HUD, right aligned below `top_right`
+    pub fn draw(&mut self, hud: &mut Hud, top_right: Vec2, scale: f32) {
+        self.entries.retain(|(_, _, at)| at.elapsed() < KILL_FEED_TIMEOUT);
+        let line_height = (CELL_HEIGHT as f32 + 2.0) * scale;
+        for (i, (message, color, at)) in self.entries.iter().enumerate() {
+            // fade over the last second
+            let left = KILL_FEED_TIMEOUT.saturating_sub(at.elapsed()).as_secs_f32();
+            let color = Vec4::new(color.x, color.y, color.z, color.w * left.min(1.0));
+            hud.text_right(message, top_right + vec2(0.0, i as f32 * line_height), scale, color);
+        }
+    }
+}
@@ -0,0 +1,342 @@
+extern crate glfw;
+use controls::{Action, Bindings};
+use glfw::{GamepadAxis, GamepadButton, JoystickId, Key, MouseButton, WindowEvent};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+const STICK_DEADZONE: f32 = 0.2; // stick travel ignored around the centre
+const TRIGGER_DEADZONE: f32 = 0.05;
+const PAD_PRESS_THRESHOLD: f32 = 0.5; // a stick or trigger counts as pressed past this
+
+const JOYSTICKS: [JoystickId; 16] = [
+    JoystickId::Joystick1, JoystickId::Joystick2, JoystickId::Joystick3, JoystickId::Joystick4,
+    JoystickId::Joystick5, JoystickId::Joystick6, JoystickId::Joystick7, JoystickId::Joystick8,
+    JoystickId::Joystick9, JoystickId::Joystick10, JoystickId::Joystick11, JoystickId::Joystick12,
+    JoystickId::Joystick13, JoystickId::Joystick14, JoystickId::Joystick15, JoystickId::Joystick16,
+];
+
+// How the mouse turns the camera, besides panning at the screen edges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseLook {
+    Off,
+    Drag,   // captured while Action::MouseLook is held
+    Toggle, // captured and released with Action::ToggleMouseLook
+}
+
+impl fmt::Display for MouseLook {
+    // Writes the mode as it is named in the settings file
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MouseLook::Off => "off",
+            MouseLook::Drag => "drag",
+            MouseLook::Toggle => "toggle",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for MouseLook {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "off" => Ok(MouseLook::Off),
+            "drag" => Ok(MouseLook::Drag),
+            "toggle" => Ok(MouseLook::Toggle),
+            _ => Err(()),
+        }
+    }
+}
+
+// Input layer, turns window events into game actions through the player's bindings
+pub struct Input {
+    pub bindings: Bindings,
+    held: HashSet<&'static str>,        // names of the keys and buttons held down
+    pad: HashMap<&'static str, f32>,    // gamepad inputs that are down, from 0 to 1
+    presses: [u32; Action::ALL.len()], // times each action was pressed this frame
+    pad_used: bool,                     // a gamepad input changed this frame
+}
+
+impl Input {
+    // Input constructor
+    pub fn new(bindings: Bindings) -> Self {
+        Input {
+            bindings,
+            held: HashSet::new(),
+            pad: HashMap::new(),
+            presses: [0; Action::ALL.len()],
+            pad_used: false,
+        }
+    }
+
+    // Method to forget this frame's presses, called before polling the next frame's events
+    pub fn begin_frame(&mut self) {
+        self.presses = [0; Action::ALL.len()];
+    }
+
+    // Method to update the input state from a window event
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match *event {
+            WindowEvent::Key(key, _, action, _) => {
+                if let Some(name) = key_name(key) {
+                    self.set(name, action);
+                }
+            }
+            WindowEvent::MouseButton(button, action, _) => {
+                if let Some(name) = mouse_button_name(button) {
+                    self.set(name, action);
+                }
+            }
+            // each notch of the wheel is a press
+            WindowEvent::Scroll(_, y) if y > 0.0 => self.press("ScrollUp"),
+            WindowEvent::Scroll(_, y) if y < 0.0 => self.press("ScrollDown"),
+            _ => {}
+        }
+    }
+
+    // Helper to track a key or button going down or up, key repeats are ignored
+    fn set(&mut self, name: &'static str, action: glfw::Action) {
+        match action {
+            glfw::Action::Press => {
+                self.held.insert(name);
+                self.press(name);
+            }
+            glfw::Action::Release => {
+                self.held.remove(name);
+            }
+            glfw::Action::Repeat => {}
+        }
+    }
+
+    // Helper to count a press of every action bound to an input
+    fn press(&mut self, name: &str) {
+        for action in self.bindings.actions_for(name) {
+            self.presses[action as usize] += 1;
+        }
+    }
+
+    // Method to read every connected gamepad, called once per frame after polling events
+    // Several gamepads are merged, each input taking the strongest value
+    pub fn poll_gamepads(&mut self, glfw: &glfw::Glfw) {
+        let mut pad = HashMap::new();
+        for id in JOYSTICKS {
+            let Some(state) = glfw.get_joystick(id).get_gamepad_state() else {
+                continue;
+            };
+            for (name, value) in gamepad_values(&state) {
+                if value > 0.0 {
+                    let v = pad.entry(name).or_insert(0.0f32);
+                    *v = v.max(value);
+                }
+            }
+        }
+
+        // sticks and triggers press their actions when pushed past the threshold
+        let pressed: Vec<&'static str> = pad
+            .keys()
+            .copied()
+            .filter(|n| pad_down(&pad, n) && !pad_down(&self.pad, n))
+            .collect();
+        for name in pressed {
+            self.press(name);
+        }
+        self.pad_used = pad != self.pad;
+        self.pad = pad;
+    }
+
+    // Method to check if a gamepad input changed this frame, so the gamepad should drive the cursor
+    pub fn pad_used(&self) -> bool {
+        self.pad_used
+    }
+
+    // Method to get how strongly an action is held, from 0 to 1
+    // Keys and buttons are all or nothing, sticks and triggers anywhere between
+    pub fn value(&self, action: Action) -> f32 {
+        self.bindings
+            .get(action)
+            .iter()
+            .map(|i| match self.held.contains(i.as_str()) {
+                true => 1.0,
+                false => self.pad.get(i.as_str()).copied().unwrap_or(0.0),
+            })
+            .fold(0.0, f32::max)
+    }
+
+    // Method to check if any input bound to an action is held down
+    pub fn held(&self, action: Action) -> bool {
+        self.value(action) > 0.0
+    }
+
+    // Method to get how many times an action was pressed this frame
+    pub fn presses(&self, action: Action) -> u32 {
+        self.presses[action as usize]
+    }
+
+    // Method to check if an action was pressed this frame
+    pub fn pressed(&self, action: Action) -> bool {
+        self.presses(action) > 0
+    }
+
+    // Method to get a value from -1 to 1 from a pair of opposing actions
+    pub fn axis(&self, positive: Action, negative: Action) -> f32 {
+        self.value(positive) - self.value(negative)
+    }
+}
+
+// Helper to get the bindings file name of a key, None for keys that can't be bound
+fn key_name(key: Key) -> Option<&'static str> {
+    Some(match key {
+        Key::A => "A",
+        Key::B => "B",
+        Key::C => "C",
+        Key::D => "D",
+        Key::E => "E",
+        Key::F => "F",
+        Key::G => "G",
+        Key::H => "H",
+        Key::I => "I",
+        Key::J => "J",
+        Key::K => "K",
+        Key::L => "L",
+        Key::M => "M",
+        Key::N => "N",
+        Key::O => "O",
+        Key::P => "P",
+        Key::Q => "Q",
+        Key::R => "R",
+        Key::S => "S",
+        Key::T => "T",
+        Key::U => "U",
+        Key::V => "V",
+        Key::W => "W",
+        Key::X => "X",
+        Key::Y => "Y",
+        Key::Z => "Z",
+        Key::Num0 => "0",
+        Key::Num1 => "1",
+        Key::Num2 => "2",
+        Key::Num3 => "3",
+        Key::Num4 => "4",
+        Key::Num5 => "5",
+        Key::Num6 => "6",
+        Key::Num7 => "7",
+        Key::Num8 => "8",
+        Key::Num9 => "9",
+        Key::F1 => "F1",
+        Key::F2 => "F2",
+        Key::F3 => "F3",
+        Key::F4 => "F4",
+        Key::F5 => "F5",
+        Key::F6 => "F6",
+        Key::F7 => "F7",
+        Key::F8 => "F8",
+        Key::F9 => "F9",
+        Key::F10 => "F10",
+        Key::F11 => "F11",
+        Key::F12 => "F12",
+        Key::Up => "Up",
+        Key::Down => "Down",
+        Key::Left => "Left",
+        Key::Right => "Right",
+        Key::Space => "Space",
+        Key::Escape => "Escape",
+        Key::Enter => "Enter",
+        Key::Tab => "Tab",
+        Key::Backspace => "Backspace",
+        Key::Insert => "Insert",
+        Key::Delete => "Delete",
+        Key::Home => "Home",
+        Key::End => "End",
+        Key::PageUp => "PageUp",
+        Key::PageDown => "PageDown",
+        Key::Comma => "Comma",
+        Key::Minus => "Minus",
+        Key::Period => "Period",
+        Key::Equal => "Equals",
+        Key::Semicolon => "Semicolon",
+        Key::Slash => "Slash",
+        Key::Backslash => "Backslash",
+        Key::LeftBracket => "OpenBracket",
+        Key::RightBracket => "CloseBracket",
+        Key::GraveAccent => "Backtick",
+        Key::LeftShift => "LeftShift",
+        Key::RightShift => "RightShift",
+        Key::LeftControl => "LeftControl",
+        Key::RightControl => "RightControl",
+        Key::LeftAlt => "LeftAlt",
+        Key::RightAlt => "RightAlt",
+        _ => return None,
+    })
+}
+
+// Helper to check if a gamepad input is pushed far enough to count as pressed
+fn pad_down(pad: &HashMap<&'static str, f32>, name: &str) -> bool {
+    pad.get(name).is_some_and(|v| *v > PAD_PRESS_THRESHOLD)
+}
+
+// Helper to get the bindings file names and values of a gamepad's inputs
+// Sticks are split into a direction per input so each can be bound to its own action
+fn gamepad_values(state: &glfw::GamepadState) -> Vec<(&'static str, f32)> {
+    let button = |b| match state.get_button_state(b) {
+        glfw::Action::Release => 0.0,
+        _ => 1.0,
+    };
+    let stick = |x, y| {
+        let v = glam::vec2(state.get_axis(x), state.get_axis(y));
+        let len = v.length();
+        if len < STICK_DEADZONE {
+            return glam::Vec2::ZERO;
+        }
+        // rescaled so the stick starts from zero at the edge of the deadzone
+        v / len * ((len - STICK_DEADZONE) / (1.0 - STICK_DEADZONE)).min(1.0)
+    };
+    // triggers rest at -1
+    let trigger = |a| {
+        let v = 0.5 * (state.get_axis(a) + 1.0);
+        if v < TRIGGER_DEADZONE { 0.0 } else { (v - TRIGGER_DEADZONE) / (1.0 - TRIGGER_DEADZONE) }
+    };
+    let left = stick(GamepadAxis::AxisLeftX, GamepadAxis::AxisLeftY);
+    let right = stick(GamepadAxis::AxisRightX, GamepadAxis::AxisRightY);
+
+    // stick y is positive downwards
+    vec!(
+        ("PadA", button(GamepadButton::ButtonA)),
+        ("PadB", button(GamepadButton::ButtonB)),
+        ("PadX", button(GamepadButton::ButtonX)),
+        ("PadY", button(GamepadButton::ButtonY)),
+        ("PadLeftBumper", button(GamepadButton::ButtonLeftBumper)),
+        ("PadRightBumper", button(GamepadButton::ButtonRightBumper)),
+        ("PadBack", button(GamepadButton::ButtonBack)),
+        ("PadStart", button(GamepadButton::ButtonStart)),
+        ("PadGuide", button(GamepadButton::ButtonGuide)),
+        ("PadLeftThumb", button(GamepadButton::ButtonLeftThumb)),
+        ("PadRightThumb", button(GamepadButton::ButtonRightThumb)),
+        ("PadDpadUp", button(GamepadButton::ButtonDpadUp)),
+        ("PadDpadDown", button(GamepadButton::ButtonDpadDown)),
+        ("PadDpadLeft", button(GamepadButton::ButtonDpadLeft)),
+        ("PadDpadRight", button(GamepadButton::ButtonDpadRight)),
+        ("PadLeftStickUp", (-left.y).max(0.0)),
+        ("PadLeftStickDown", left.y.max(0.0)),
+        ("PadLeftStickLeft", (-left.x).max(0.0)),
+        ("PadLeftStickRight", left.x.max(0.0)),
+        ("PadRightStickUp", (-right.y).max(0.0)),
+        ("PadRightStickDown", right.y.max(0.0)),
+        ("PadRightStickLeft", (-right.x).max(0.0)),
+        ("PadRightStickRight", right.x.max(0.0)),
+        ("PadLeftTrigger", trigger(GamepadAxis::AxisLeftTrigger)),
+        ("PadRightTrigger", trigger(GamepadAxis::AxisRightTrigger)),
+    )
+}
+
+// Helper to get the bindings file name of a mouse button
+fn mouse_button_name(button: MouseButton) -> Option<&'static str> {
+    Some(match button {
+        MouseButton::Button1 => "MouseLeft",
+        MouseButton::Button2 => "MouseRight",
+        MouseButton::Button3 => "MouseMiddle",
+        MouseButton::Button4 => "Mouse4",
+        MouseButton::Button5 => "Mouse5",
+        _ => return None,
+    })
+}
@@ -1,29 +1,48 @@
+pub mod assets;
+pub mod audio;
+pub mod bounds;
 pub mod camera;
 pub mod entities;
-pub mod keys;
+pub mod hud;
+pub mod input;
+pub mod map;
 pub mod meshloader;
+pub mod minimap;
 pub mod music;
+pub mod renderer;
+pub mod settings;
 pub mod shader;
+pub mod terrain;
 
+use assets::Assets;
+use audio::{Audio, Emitter, Rumbles, Sound};
+use camera::CameraMode;
+use controls::{Action, Bindings};
+use input::{Input, MouseLook};
 use entities::*;
-use meshloader::Mesh;
+use hud::{Hud, KillFeed};
+use map::MapConfig;
+use meshloader::UvMode;
+use minimap::Minimap;
+use music::{Cue, MusicPlayer};
 use rand::{thread_rng, Rng};
+use renderer::{FrameUniforms, GlBackend, ProgramHandle, Renderer, Skybox};
+use settings::Settings;
 use shader::ShaderProgram;
+use terrain::Terrain;
 
 use glam::f32::Vec3A;
 use glam::Vec3Swizzles;
-use glam::{vec3a, vec4};
+use glam::{vec2, vec3a, vec4};
 use glfw::Context;
 use glfw::Cursor;
 use glfw::StandardCursor::*;
 use rand::rngs::ThreadRng;
+use std::collections::HashSet;
 use std::{env, f32::consts::PI, time};
 
-// sound
-use rodio::OutputStream; // dont remove import, for comme
-
 // net, tokio, messaging
-use messaging::{AsBytes, Command, Message};
+use messaging::{AsBytes, Command, Message, Phase, NO_PLAYER};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
 use std::sync::Arc;
@@ -32,18 +51,85 @@ use tokio::net::UdpSocket;
 const DELTA_TIME: time::Duration = time::Duration::from_millis(1);
 const ORIGIN: Vec3A = vec3a(0.0, 0.0, 0.0);
 const MOVEMENT_DELTA: f32 = 0.005;
-const CAMERA_DELTA: f32 = 0.03;
+const CAMERA_SPIN_RATE: f32 = 1.8; // radians per second
+const CAMERA_TILT_RATE: f32 = 1.2; // radians per second
 const PAN_TRESHOLD_RATIO: f64 = 0.01; //how close to the edge before panning
 const TILT_TRESHOLD_RATIO: f64 = 0.01; //how close to the edge before tilting
 const ZOOM_DELTA: f32 = 0.1;
+const ROLL_FULL_SPEED: f32 = 6.0; // units per second at which rolling is loudest
+const ROLL_SMOOTHING_RATE: f32 = 8.0; // per second, for other players' rolling
+const IMPACT_FULL_SPEED: f32 = 0.05; // change in velocity per frame at which impacts are loudest
+const IMPACT_MIN: f32 = 0.2; // quieter impacts are not played
+const SPHERE_CONTACT_DISTANCE: f32 = 0.2;
+const PAD_CURSOR_SPEED: f64 = 0.6; // screen heights per second
+const PAD_CURSOR_MARGIN_RATIO: f64 = 0.02; // keeps the gamepad cursor away from the panning edges
 const GROUND_IMMUTABLE_RADIUS: f32 = 1.5;
 const PLAYER_SPAWN_RADIUS: f32 = 10.0;
 // const CUBE_SPAWN_RADIUS: f32 = 5.0;
 // const CUBE_RESPAWN_TIME: u64 = 60;
 const MAX_LIGHTS: usize = 16;
+const LIGHTING_PROGRAM: ProgramHandle = ProgramHandle(0);
+const INSTANCED_PROGRAM: ProgramHandle = ProgramHandle(1);
+const SHADOW_PROGRAM: ProgramHandle = ProgramHandle(2);
+const SHADOW_INSTANCED_PROGRAM: ProgramHandle = ProgramHandle(3);
+const SKYBOX_PROGRAM: ProgramHandle = ProgramHandle(4);
+const MAP_NAME: &str = "default";
+const TERRAIN_CHUNKS: usize = 8; // per side
+const TERRAIN_LOD_DISTANCE: f32 = 12.0;
 const LOCAL_IP_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
 const SERVER_PORT: u16 = 42069;
-const ENEMY_COLOR: Vec3A = vec3a(0.6, 0.1, 0.8);
+const HUD_SCALE: f32 = 4.0; // screen pixels per font pixel
+const HUD_MARGIN: f32 = 24.0;
+const MINIMAP_SIZE: f32 = 256.0;
+
+// Match phase as the server last reported it, shared between the network and game tasks
+#[derive(Default)]
+struct MatchState {
+    phase: AtomicU8,
+    round: AtomicU32,
+    remaining_ms: AtomicU32, // left in the countdown or results
+    winner: AtomicU8,
+    ready: AtomicU64, // bit per player
+}
+
+// MatchState methods
+impl MatchState {
+
+    // Getter for the match phase
+    fn phase(&self) -> Phase {
+        Phase::from_repr(self.phase.load(Ordering::Relaxed)).unwrap_or(Phase::Waiting)
+    }
+
+    // Method to store the phase, round and time left sent by the server
+    fn store_phase(&self, m: &Message, offset: usize) {
+        if let (Some(phase), Some(round), Some(remaining)) =
+            (m.extract_u8(offset), m.extract_u32(offset + 1), m.extract_u32(offset + 5))
+        {
+            self.round.store(round, Ordering::Relaxed);
+            self.remaining_ms.store(remaining, Ordering::Relaxed);
+            self.phase.store(phase, Ordering::Relaxed);
+        }
+    }
+
+    // Method to check if a player is ready for the next round
+    fn is_ready(&self, pid: u8) -> bool {
+        1u64.checked_shl(pid as u32).is_some_and(|bit| self.ready.load(Ordering::Relaxed) & bit != 0)
+    }
+}
+
+// Player id and x, y, z position bits of a remote player, as the server last reported them
+type PlayerPosition = (Arc<AtomicU8>, Arc<AtomicU32>, Arc<AtomicU32>, Arc<AtomicU32>);
+
+// State written by the network task and read by the game loop
+#[derive(Clone)]
+struct Shared {
+    counter: Arc<AtomicU64>,
+    num_players: Arc<AtomicU8>,
+    gnd_muts: Vec<(Arc<AtomicU32>, Arc<AtomicU32>)>,
+    player_positions: Vec<PlayerPosition>,
+    scoreboard: Vec<(Arc<AtomicU8>, Arc<AtomicU8>)>,
+    match_state: Arc<MatchState>,
+}
 
 #[tokio::main]
 async fn main() -> tokio::io::Result<()> {
@@ -59,6 +145,13 @@ async fn main() -> tokio::io::Result<()> {
             Arc::new(AtomicU32::new(0)),
         ));
     }
+    // points and lives of each player, as the server last reported them
+    let mut scoreboard = vec![];
+    for _ in 0..64 {
+        scoreboard.push((Arc::new(AtomicU8::new(0)), Arc::new(AtomicU8::new(0))));
+    }
+    let match_state = Arc::new(MatchState::default());
+    match_state.winner.store(NO_PLAYER, Ordering::Relaxed);
     let mut gnd_muts = vec![];
     gnd_muts.push((Arc::new(AtomicU32::new(0)), Arc::new(AtomicU32::new(64))));
     for _ in 1..64 {
@@ -70,10 +163,13 @@ async fn main() -> tokio::io::Result<()> {
         args.push(arg);
     }
 
+    // --dev turns on hot reloading of shaders, meshes and textures
+    let dev_mode = args.iter().any(|a| a == "--dev");
+
     let server_socket: SocketAddr = SocketAddr::new(
         args[1]
             .parse()
-            .expect(&format!("Invalid IP: {}", args[1]).to_string()),
+            .unwrap_or_else(|_| panic!("Invalid IP: {}", args[1])),
         SERVER_PORT,
     );
 
@@ -98,24 +194,20 @@ async fn main() -> tokio::io::Result<()> {
     match m.command {
         Command::SETPID => {
             let pid = m.extract_u8(0).unwrap();
+            let lives = m.extract_u8(1).unwrap_or(1);
+            let win_score = m.extract_u8(2).unwrap_or(1);
+            scoreboard[pid as usize].1.store(lives, Ordering::Relaxed);
+            let shared = Shared {
+                counter,
+                num_players,
+                gnd_muts,
+                player_positions,
+                scoreboard,
+                match_state,
+            };
             let _ = tokio::join!(
-                game(
-                    &socket,
-                    counter.clone(),
-                    &listener,
-                    pid,
-                    num_players.clone(),
-                    gnd_muts.clone(),
-                    player_positions.clone()
-                ),
-                listen(
-                    &socket,
-                    counter.clone(),
-                    num_players.clone(),
-                    pid,
-                    gnd_muts.clone(),
-                    player_positions.clone()
-                ),
+                game(&socket, &listener, pid, shared.clone(), win_score, dev_mode),
+                listen(&socket, pid, shared),
             );
         }
         _ => todo!(),
@@ -124,19 +216,15 @@ async fn main() -> tokio::io::Result<()> {
     Ok(())
 }
 
-async fn listen(
-    socket: &UdpSocket,
-    counter: Arc<AtomicU64>,
-    num_players: Arc<AtomicU8>,
-    pid: u8,
-    gnd_muts: Vec<(Arc<AtomicU32>, Arc<AtomicU32>)>,
-    player_positions: Vec<(
-        Arc<AtomicU8>,
-        Arc<AtomicU32>,
-        Arc<AtomicU32>,
-        Arc<AtomicU32>,
-    )>,
-) -> tokio::io::Result<()> {
+async fn listen(socket: &UdpSocket, pid: u8, shared: Shared) -> tokio::io::Result<()> {
+    let Shared {
+        counter,
+        num_players,
+        gnd_muts,
+        player_positions,
+        scoreboard,
+        match_state,
+    } = shared;
     loop {
         let mut m = Message::new(Command::STATE);
         m.push_bytes(pid.as_bytes());
@@ -155,22 +243,41 @@ async fn listen(
         let m = Message::try_from_data(peer, &buf[..size]).unwrap();
         match m.command {
             Command::RSTATE => {
-                num_players.store(m.extract_u8(0).unwrap(), Ordering::Relaxed);
+                let np = m.extract_u8(0).unwrap();
+                num_players.store(np, Ordering::Relaxed);
                 let num_mutations = m.extract_u64(1).unwrap();
                 if counter.load(Ordering::Relaxed) < num_mutations {
                     counter.store(num_mutations, Ordering::Relaxed);
                     //println!("{:?}", counter);
                 }
+                // the phase and result are repeated here in case their messages were lost
+                match_state.store_phase(&m, 9);
+                if let (Some(winner), Some(ready)) = (m.extract_u8(18), m.extract_u64(19)) {
+                    match_state.winner.store(winner, Ordering::Relaxed);
+                    match_state.ready.store(ready, Ordering::Relaxed);
+                }
+                for (idx, (p, l)) in scoreboard.iter().enumerate().take(np as usize) {
+                    if let (Some(points), Some(lives)) = (m.extract_u8(27 + 2 * idx), m.extract_u8(28 + 2 * idx)) {
+                        p.store(points, Ordering::Relaxed);
+                        l.store(lives, Ordering::Relaxed);
+                    }
+                }
             }
+            Command::MATCHEND => {
+                if let Some(winner) = m.extract_u8(0) {
+                    match_state.winner.store(winner, Ordering::Relaxed);
+                }
+            }
+            Command::PHASE => match_state.store_phase(&m, 0),
             Command::RPPOS => {
                 if let Some(np) = m.extract_u8(0) {
                     for idx in 0..np {
                         if let Some(ppos) = m.extract_vec3a((12 * idx + 1).into()) {
                             let (i, x, y, z) = &player_positions[idx as usize];
-                            i.store(idx.into(), Ordering::Relaxed);
-                            x.store(ppos.x.to_bits().into(), Ordering::Relaxed);
-                            y.store(ppos.y.to_bits().into(), Ordering::Relaxed);
-                            z.store(ppos.z.to_bits().into(), Ordering::Relaxed);
+                            i.store(idx, Ordering::Relaxed);
+                            x.store(ppos.x.to_bits(), Ordering::Relaxed);
+                            y.store(ppos.y.to_bits(), Ordering::Relaxed);
+                            z.store(ppos.z.to_bits(), Ordering::Relaxed);
                         }
                     }
                 }
@@ -179,8 +286,8 @@ async fn listen(
                 if let Some(amt) = m.extract_f32(5) {
                     let idx = m.extract_u32(1).unwrap();
                     let (i, a) = &gnd_muts[1];
-                    i.store(idx.into(), Ordering::Relaxed);
-                    a.store(amt.to_bits().into(), Ordering::Relaxed);
+                    i.store(idx, Ordering::Relaxed);
+                    a.store(amt.to_bits(), Ordering::Relaxed);
                     //dbg!(&gnd_muts[1]);
                 }
             }
@@ -191,21 +298,22 @@ async fn listen(
 
 async fn game(
     socket: &UdpSocket,
-    counter: Arc<AtomicU64>,
     listener: &UdpSocket,
     pid: u8,
-    num_players: Arc<AtomicU8>,
-    gnd_muts: Vec<(Arc<AtomicU32>, Arc<AtomicU32>)>,
-    player_positions: Vec<(
-        Arc<AtomicU8>,
-        Arc<AtomicU32>,
-        Arc<AtomicU32>,
-        Arc<AtomicU32>,
-    )>,
+    shared: Shared,
+    win_score: u8,
+    dev_mode: bool,
 ) -> tokio::io::Result<()> {
+    let Shared {
+        num_players,
+        gnd_muts,
+        player_positions,
+        scoreboard,
+        match_state,
+        ..
+    } = shared;
     let mut scr_w = 1920i32;
     let mut scr_h = 1080i32;
-    let mut framenum = 0u64;
 
     let mut rng = thread_rng();
 
@@ -221,31 +329,45 @@ async fn game(
         f32::atan2(player_init_pos.x, player_init_pos.z),
     );
 
-    let mut myscore = 0;
-    let mut myhealth = 3;
+    let settings = Settings::load();
 
-    let (_stream, stream_handle) = OutputStream::try_default().unwrap();
+    let mut assets = Assets::new()?;
+    if dev_mode {
+        assets.enable_hot_reload();
+    }
+    let mut audio = Audio::new(&assets, &settings);
+    let mut rumbles = Rumbles::new(&audio);
+    let mut music = MusicPlayer::new(&assets);
+    let own_roll = audio.emitter(Sound::Roll);
+    let mut remote_contacts: HashSet<(usize, usize)> = HashSet::new();
 
     // initializing entities as Entity
     let mut player = Player::new(
-        "assets/mesh/small_sphere.stl",
+        &mut assets,
+        "mesh/small_sphere.stl",
         player_init_pos,
         1.0 * vec3a(0.1, 0.5, 0.2),
         player_init_cam,
         1.0,
         pid,
-    );
+    )?;
+    player.entity.set_uv_mode(UvMode::Spherical);
+    player.entity.material.specular = 0.8; // glossy
+    player.entity.material.shininess = 64.0;
+    player.camera.apply_settings(&settings);
 
-    let mut goal_2d = Entity::new("assets/mesh/3.stl", ORIGIN, 1.0 * vec3a(0.2, 0.2, 0.2), 1.0);
+    let mut goal_2d = Entity::new(&mut assets, "mesh/3.stl", ORIGIN, 1.0 * vec3a(0.2, 0.2, 0.2), 1.0)?;
     goal_2d.set_scale(0.01, 0.01, 0.01);
 
     let mut goal = Entity::new(
-        "assets/mesh/rt_marker.stl",
+        &mut assets,
+        "mesh/rt_marker.stl",
         ORIGIN + vec3a(0.0, 0.0, 0.0),
         1.0 * vec3a(0.8, 0.8, 0.2),
         1.0,
-    );
+    )?;
     goal.set_scale(2.0, 2.0, 2.0);
+    goal.material.emissive = vec3a(0.5, 0.45, 0.1); // glow so the goal stands out
 
     // let theta2 = rng.gen_range(0.0..2.0*PI);
     // let cube_r = rng.gen_range(2.0..=CUBE_SPAWN_RADIUS);
@@ -254,52 +376,54 @@ async fn game(
     let mut other_player_entities = vec![];
     for _ in 0..pid {
         let newplayer = Entity::new(
-            "assets/mesh/small_sphere.stl",
+            &mut assets,
+            "mesh/small_sphere.stl",
             ORIGIN,
             randcolor(&mut rng),
             1.0,
-        );
-        let score = Entity::new("assets/mesh/3.stl", ORIGIN, ENEMY_COLOR, 1.0);
-        other_player_entities.push((newplayer, score));
+        )?;
+        other_player_entities.push(newplayer);
     }
 
     let mut newplayer = Entity::new(
-        "assets/mesh/small_sphere.stl",
+        &mut assets,
+        "mesh/small_sphere.stl",
         ORIGIN,
         vec3a(0.1, 0.1, 0.8),
         1.0,
-    );
+    )?;
     newplayer.set_scale(0.0, 0.0, 0.0);
-    let mut score = Entity::new("assets/mesh/3.stl", ORIGIN, vec3a(0.8, 0.1, 0.8), 1.0);
-    score.set_scale(0.0, 0.0, 0.0);
-    other_player_entities.push((newplayer, score));
-
-    let mut score_stl = Entity::new("assets/mesh/0.stl", ORIGIN, vec3a(0.1, 0.5, 0.2), 1.0);
-
-    let mut myhearts = vec![];
-    for _ in 0..3 {
-        let mut heart_stl =
-            Entity::new("assets/mesh/heart.stl", ORIGIN, vec3a(0.8, 0.2, 0.2), 1.0); //spawn new heart with red colour
-        heart_stl.set_scale(0.5, 0.5, 0.5);
-        myhearts.push(heart_stl);
-    }
+    other_player_entities.push(newplayer);
+    // indexed like other_player_entities, with the last position and the smoothed roll volume
+    let mut remote_rolls: Vec<(Emitter, Option<Vec3A>, f32)> =
+        other_player_entities.iter().map(|_| (audio.emitter(Sound::Roll), None, 0.0)).collect();
 
     let mut ground = Entity::new(
-        "assets/mesh/ground.stl",
+        &mut assets,
+        "mesh/ground.stl",
         ORIGIN,
-        vec3a(0.47, 0.41, 0.34),
+        1.1 * vec3a(0.47, 0.41, 0.34),
         0.0,
-    );
-    ground.set_texture_id(1);
+    )?;
+    ground.set_texture("dirt");
+    ground.set_uv_mode(UvMode::Planar);
     ground.set_scale(3.0, 1.0, 3.0);
-    ground.reflectance = 1.1;
+    let mut terrain = Terrain::new(
+        &mut assets,
+        ground,
+        "mesh/ground_lowpoly.stl",
+        TERRAIN_CHUNKS,
+        TERRAIN_LOD_DISTANCE,
+    )?;
+    let mut minimap = Minimap::new(&terrain.ground, MINIMAP_SIZE, goal.pos, GROUND_IMMUTABLE_RADIUS);
 
     let mut rt_marker = Entity::new(
-        "assets/mesh/rt_marker.stl",
+        &mut assets,
+        "mesh/rt_marker.stl",
         ORIGIN,
         1.0 * vec3a(0.2, 0.2, 0.2),
         1.0,
-    );
+    )?;
     rt_marker.set_scale(2.0, 2.0, 2.0);
 
     //println!("{:?}", ground.mesh.vertices.len());
@@ -326,7 +450,8 @@ async fn game(
     let mut ground_vertex_markers = vec!();
     for vertex in &ground.mesh.vertices {
         let mut marker = Entity::new(
-            "assets/mesh/cube.stl",
+            &mut assets,
+            "mesh/cube.stl",
             *vertex,
             vec3a(0.8, 0.2, 0.8),
             0.0,
@@ -336,8 +461,12 @@ async fn game(
     }
     */
 
-    //keys
-    let mut keystates = [0; 16];
+    //keys, mouse buttons and scrolling go through the player's action bindings
+    let (bindings, binding_errors) = Bindings::load();
+    for e in binding_errors {
+        println!("{}: {}", controls::bindings_path().display(), e);
+    }
+    let mut input = Input::new(bindings);
     let mut glfw = glfw::init(glfw::fail_on_errors).unwrap();
 
     let (mut window, events) = glfw
@@ -361,71 +490,125 @@ async fn game(
     window.make_current();
     gl::load_with(|f_name| window.get_proc_address(f_name));
 
-    let lighting_program: ShaderProgram;
-
     // lighting
-    let mut light_colors: [f32; MAX_LIGHTS * 3] = [0.0; MAX_LIGHTS * 3];
-    let mut light_positions: [f32; MAX_LIGHTS * 3] = [0.0; MAX_LIGHTS * 3];
-
-    let light_dist = 100f32;
-
-    for i in 0..MAX_LIGHTS {
-        let theta: f32 = 2.0 * PI / (MAX_LIGHTS as f32) * (i as f32);
-        let (x, y, z) = (
-            light_dist * f32::cos(theta),
-            50.0,
-            light_dist * f32::sin(theta),
-        );
-        light_positions[3 * i] = x;
-        light_positions[3 * i + 1] = y;
-        light_positions[3 * i + 2] = z;
-        light_colors[3 * i] = 4.0 / (MAX_LIGHTS as f32);
-        light_colors[3 * i + 1] = 4.0 / (MAX_LIGHTS as f32);
-        light_colors[3 * i + 2] = 4.0 / (MAX_LIGHTS as f32);
-    }
-
+    let mut map = MapConfig::load(&assets, MAP_NAME, MAX_LIGHTS)?;
+    assets.watch(&map.path);
+    let match_start = time::Instant::now();
+
+    let mut renderer;
+    let mut sky_cubemap;
+    let mut hud;
+    let mut kill_feed = KillFeed::default();
     unsafe {
         gl::Enable(gl::DEPTH_TEST);
-        gl::ClearColor(0.52, 0.81, 0.92, 1.0);
-        lighting_program = ShaderProgram::new(
-            "client/src/shaders/lighting.vs",
-            "client/src/shaders/lighting.fs",
-        );
-        lighting_program.set_vec3fv(b"lightColor\0", MAX_LIGHTS, &light_colors[0]);
-        lighting_program.set_vec3fv(b"lightPos\0", MAX_LIGHTS, &light_positions[0]);
-
-        //init graphics (vertices, normals, textures)
-        player.entity.gl_init();
-        for (pe, score) in &mut other_player_entities {
-            pe.gl_init();
-            score.gl_init();
-        }
-        goal.gl_init();
-        ground.gl_init();
-        rt_marker.gl_init();
-        goal_2d.gl_init();
-        score_stl.gl_init();
-
-        for e in &mut myhearts {
-            e.gl_init();
+        let max_lights = ("MAX_LIGHTS", MAX_LIGHTS.to_string());
+        let instanced = ("INSTANCED", String::from("1"));
+        // indices match the *_PROGRAM handles
+        let programs = vec![
+            load_program(&mut assets, "lighting", std::slice::from_ref(&max_lights))?,
+            load_program(&mut assets, "lighting", &[max_lights, instanced.clone()])?,
+            load_program(&mut assets, "shadow", &[])?,
+            load_program(&mut assets, "shadow", &[instanced])?,
+            load_program(&mut assets, "skybox", &[])?,
+        ];
+        let mut backend = GlBackend::new(programs);
+        backend.shadow_map_size = settings.shadow_map_size as i32;
+        renderer = Renderer::new(backend);
+        renderer.set_instanced(INSTANCED_PROGRAM);
+        renderer.set_instanced(SHADOW_INSTANCED_PROGRAM);
+        renderer.set_shadow_program(LIGHTING_PROGRAM, SHADOW_PROGRAM);
+        renderer.set_shadow_program(INSTANCED_PROGRAM, SHADOW_INSTANCED_PROGRAM);
+        sky_cubemap = load_sky(&mut assets, &map)?;
+        hud = Hud::new(load_program(&mut assets, "hud", &[])?);
+
+        //init textures, meshes are uploaded by the renderer on first draw
+        player.entity.gl_init(&mut assets)?;
+        for pe in &mut other_player_entities {
+            pe.gl_init(&mut assets)?;
         }
+        goal.gl_init(&mut assets)?;
+        terrain.ground.gl_init(&mut assets)?;
+        rt_marker.gl_init(&mut assets)?;
+        goal_2d.gl_init(&mut assets)?;
 
         /* initialize ground vertex marker cubes
             for marker in &mut ground_vertex_markers {
-            marker.gl_init();
+            marker.gl_init(&mut assets)?;
             }
         */
     }
 
+    // the shadow map covers the whole ground, with headroom for raised terrain
+    let shadow_radius = (0..terrain.ground.mesh.vertices.len())
+        .map(|i| terrain.ground.world_vertex(i).length())
+        .fold(0.0, f32::max)
+        + 5.0;
+
+    let mut last_frame = time::Instant::now();
+    let mut last_scores: Vec<(u8, u8)> = vec![(0, 0); scoreboard.len()];
+    let mut eliminated = false;
+    let mut last_phase = Phase::Waiting;
+    let mut last_round = 0;
+    let mut round_start = time::Instant::now();
+    let mut last_camera_mode = player.camera.mode;
+    let mut mouse_look_toggled = false;
+    let mut cursor_captured = false;
+    let mut last_cursor = window.get_cursor_pos();
+    let mut pad_cursor: Option<(f64, f64)> = None; // virtual cursor, used after a gamepad is touched
+
     //loop
     while !window.should_close() {
-        //increase frame number
-        framenum += 1;
+        let dt = last_frame.elapsed().as_secs_f32();
+        last_frame = time::Instant::now();
         
+        input.begin_frame();
         glfw.poll_events();
         window.glfw.set_swap_interval(glfw::SwapInterval::Adaptive);
         for (_, event) in glfw::flush_messages(&events) {
-            handle_window_event(&mut glfw, &mut window, event, &mut player, &mut keystates);
+            input.handle_event(&event);
+        }
+        input.poll_gamepads(&glfw);
+        handle_actions(&mut glfw, &mut window, &input, &mut player, &mut mouse_look_toggled);
+
+        // dev mode hot reload
+        let changed = unsafe { assets.poll_changes() };
+        if !changed.is_empty() {
+            if changed.contains(&map.path) {
+                match MapConfig::load(&assets, MAP_NAME, MAX_LIGHTS) {
+                    Ok(new_map) => {
+                        println!("Reloaded map {}", map.path.display());
+                        map = new_map;
+                        match unsafe { load_sky(&mut assets, &map) } {
+                            Ok(cubemap) => sky_cubemap = cubemap,
+                            Err(e) => println!("Skybox reload failed, keeping old skybox: {}", e),
+                        }
+                    }
+                    Err(e) => println!("Map reload failed, keeping old map: {}", e),
+                }
+            }
+            for program in renderer.backend.programs.iter_mut().chain([&mut hud.program]) {
+                if !changed.iter().any(|p| program.sources.contains(p)) {
+                    continue;
+                }
+                match unsafe { program.reload() } {
+                    Ok(()) => {
+                        println!("Reloaded shaders");
+                        for source in &program.sources {
+                            assets.watch(source);
+                        }
+                    }
+                    Err(e) => println!("Shader reload failed, keeping old program: {}", e),
+                }
+            }
+            if let Err(e) = terrain.reload_mesh(&mut assets, &changed) {
+                println!("Terrain reload failed: {}", e);
+            }
+            minimap.update_heights(&terrain.ground);
+            let mut entities = vec![&mut player.entity, &mut goal, &mut goal_2d, &mut rt_marker];
+            entities.extend(&mut other_player_entities);
+            for e in entities {
+                e.reload_mesh(&assets, &changed);
+            }
         }
 
         //update aspect ratio to window size
@@ -433,51 +616,91 @@ async fn game(
         player.camera.camera_bare.aspect = scr_w as f32 / scr_h as f32;
         unsafe { gl::Viewport(0, 0, scr_w, scr_h) }
 
-        let (x, y) = window.get_cursor_pos();
-        
+        // mouse look captures the cursor, ground is then picked at the centre of the screen
+        let looking = match settings.mouse_look {
+            MouseLook::Off => false,
+            MouseLook::Drag => input.held(Action::MouseLook),
+            MouseLook::Toggle => mouse_look_toggled,
+        };
+        if looking != cursor_captured {
+            cursor_captured = looking;
+            let mode = if looking { glfw::CursorMode::Disabled } else { glfw::CursorMode::Normal };
+            window.set_cursor_mode(mode);
+            last_cursor = window.get_cursor_pos();
+        }
+        let cursor = window.get_cursor_pos();
+        let look_delta = (cursor.0 - last_cursor.0, cursor.1 - last_cursor.1);
+        last_cursor = cursor;
+
+        // the gamepad moves a virtual cursor until the mouse is moved again
+        if input.pad_used() && pad_cursor.is_none() {
+            pad_cursor = Some((0.5 * scr_w as f64, 0.5 * scr_h as f64));
+        } else if look_delta != (0.0, 0.0) {
+            pad_cursor = None;
+        }
+        if let Some((px, py)) = &mut pad_cursor {
+            let step = PAD_CURSOR_SPEED * scr_h as f64 * dt as f64;
+            let (mx, my) = (PAD_CURSOR_MARGIN_RATIO * scr_w as f64, PAD_CURSOR_MARGIN_RATIO * scr_h as f64);
+            *px = (*px + step * input.axis(Action::CursorRight, Action::CursorLeft) as f64).clamp(mx, scr_w as f64 - mx);
+            *py = (*py + step * input.axis(Action::CursorDown, Action::CursorUp) as f64).clamp(my, scr_h as f64 - my);
+        }
+        let (x, y) = match (looking, pad_cursor) {
+            (true, _) => (0.5 * scr_w as f64, 0.5 * scr_h as f64),
+            (false, Some(pad)) => pad,
+            (false, None) => cursor,
+        };
+
         // ground mesh selection / mouse tracking using rt_marker
-        let raycast = cursor_raycast_and_animate(&mut player, x, scr_w, y, scr_h, &mut ground, &mut rt_marker, &mut window);
-
-        //send intention to mutate
-        if keystates[10] == 1 || keystates[11] == 1 {
-            if rt_marker.pos.xz().distance(ORIGIN.xz()) >= GROUND_IMMUTABLE_RADIUS {
-                let mut m = Message::new(Command::MUT);
-                if keystates[10] == 1 && keystates[11] == 0 {
-                    m.push_bytes(player.player_id.as_bytes());
-                    m.push_bytes((raycast as u32).as_bytes());
-                    m.push_bytes(player.ability.ground_mut_power.as_bytes());
-                    listener.send(&m.get_bytes()).await?;
-                }
-                if keystates[10] == 0 && keystates[11] == 1 {
-                    m.push_bytes(player.player_id.as_bytes());
-                    m.push_bytes((raycast as u32).as_bytes());
-                    m.push_bytes((-player.ability.ground_mut_power).as_bytes());
-                    listener.send(&m.get_bytes()).await?;
-                }
+        let raycast = cursor_raycast_and_animate(&mut player, (x, y), (scr_w, scr_h), &mut terrain.ground, &mut rt_marker, &mut window);
+
+        //send intention to mutate, triggers edit with their analogue strength
+        let (raise, lower) = (input.held(Action::RaiseGround), input.held(Action::LowerGround));
+        let power = player.ability.ground_mut_power
+            * input.value(Action::RaiseGround).max(input.value(Action::LowerGround));
+        if (raise || lower) && rt_marker.pos.xz().distance(ORIGIN.xz()) >= GROUND_IMMUTABLE_RADIUS {
+            let mut m = Message::new(Command::MUT);
+            if raise && !lower {
+                m.push_bytes(player.player_id.as_bytes());
+                m.push_bytes((raycast as u32).as_bytes());
+                m.push_bytes(power.as_bytes());
+                listener.send(&m.get_bytes()).await?;
+            }
+            if !raise && lower {
+                m.push_bytes(player.player_id.as_bytes());
+                m.push_bytes((raycast as u32).as_bytes());
+                m.push_bytes((-power).as_bytes());
+                listener.send(&m.get_bytes()).await?;
             }
         }
 
         //mutate mesh
         let gvec = &gnd_muts;
         let (idx, amt) = &gvec[1];
-        ground.mesh.mutate(
-            idx.load(Ordering::Relaxed) as usize,
-            vec3a(0.0, 1.0, 0.0),
-            f32::from_bits(amt.load(Ordering::Relaxed)),
-        );
+        let amount = f32::from_bits(amt.load(Ordering::Relaxed));
+        if amount != 0.0 {
+            let idx = idx.load(Ordering::Relaxed) as usize;
+            terrain.mutate(idx, vec3a(0.0, 1.0, 0.0), amount);
+            rumbles.edit(terrain.ground.world_vertex(idx), amount.abs() / player.ability.ground_mut_power);
+            minimap.update_heights(&terrain.ground);
+        }
         idx.store(0, Ordering::Relaxed);
         amt.store(0, Ordering::Relaxed);
         
-        // player movement
-        player.mv(vec3a(
-            (keystates[0] - keystates[2]) as f32
-                * -MOVEMENT_DELTA
-                * f32::sin(player.camera.camera_angle),
-            0.0, // use camera angle as direction
-            (keystates[0] - keystates[2]) as f32
-                * -MOVEMENT_DELTA
-                * f32::cos(player.camera.camera_angle),
-        )); // for the player to move towards
+        // player movement, the spectator camera flies with the movement keys instead and strafes
+        // with the spin keys
+        let forward = input.axis(Action::Forward, Action::Backward);
+        if player.camera.mode == CameraMode::Spectator {
+            let (angle, tilt) = (player.camera.camera_angle, player.camera.tilt);
+            let right = input.axis(Action::SpinRight, Action::SpinLeft);
+            let up = input.axis(Action::FlyUp, Action::FlyDown);
+            player.camera.spectator.fly(angle, tilt, forward, right, up, dt);
+        } else {
+            player.mv(vec3a(
+                forward * -MOVEMENT_DELTA * f32::sin(player.camera.camera_angle),
+                0.0, // use camera angle as direction
+                forward * -MOVEMENT_DELTA * f32::cos(player.camera.camera_angle),
+            )); // for the player to move towards
+        }
         player.mvhelper();
         
         //init emeny spheres
@@ -491,17 +714,17 @@ async fn game(
         }
         if np > other_player_entities.len() as u8 {
             let mut newplayer = Entity::new(
-                "assets/mesh/small_sphere.stl",
+                &mut assets,
+                "mesh/small_sphere.stl",
                 ORIGIN,
                 randcolor(&mut rng),
                 1.0,
-            );
-            let mut score = Entity::new("assets/mesh/3.stl", ORIGIN, ENEMY_COLOR, 1.0);
+            )?;
             unsafe {
-                newplayer.gl_init();
-                score.gl_init();
+                newplayer.gl_init(&mut assets)?;
             }
-            other_player_entities.push((newplayer, score));
+            other_player_entities.push(newplayer);
+            remote_rolls.push((audio.emitter(Sound::Roll), None, 0.0));
         }
 
         //get enemy position
@@ -517,8 +740,7 @@ async fn game(
             let x = f32::from_bits(ux.load(Ordering::Relaxed));
             let y = f32::from_bits(uy.load(Ordering::Relaxed));
             let z = f32::from_bits(uz.load(Ordering::Relaxed));
-            other_player_entities[pid as usize].0.pos = vec3a(x, y, z);
-            other_player_entities[pid as usize].1.pos = vec3a(x, y, z) + vec3a(0.0, 0.3, 0.0);
+            other_player_entities[pid as usize].pos = vec3a(x, y, z);
         }
         
         /* 
@@ -532,22 +754,22 @@ async fn game(
         let has_goal = player.detect_col(&goal).0;
 
         //collision detection for ground
-        if player.detect_col(&ground).0 {
-            player.collide(&ground);
+        if player.detect_col(&terrain.ground).0 {
+            player.collide(&terrain.ground);
             player.on_ground = true;
         };
 
         //collision detection for other players
-        for i in 0..other_player_entities.len() {
-            if i != player.player_id.into() {
-                if player.detect_col(&other_player_entities[i].0).0 {
-                    player.collide(&other_player_entities[i].0);
+        for (i, pe) in other_player_entities.iter().enumerate() {
+            if i != usize::from(player.player_id) && player.detect_col(pe).0 {
+                let before = player.vec;
+                player.collide(pe);
+                let impact = (player.vec - before).length() / IMPACT_FULL_SPEED;
+                // resting against another sphere keeps colliding a little every frame
+                if impact > IMPACT_MIN {
+                    audio.play_at(Sound::Impact, player.pos(), impact.min(1.0));
                 }
             }
-            other_player_entities[i]
-                .1
-                .mesh
-                .rotate_y(0.15 * framenum as f32);
         }
     
         /* 
@@ -557,48 +779,87 @@ async fn game(
          */
 
         
-        //respawn
-        if has_goal || player.entity.pos.y < -5.0 {
-            let theta = rng.gen_range(0.0..2.0 * PI);
-            let theta2 = rng.gen_range(0.0..2.0 * PI);
-            let player_init_pos = vec3a(
-                PLAYER_SPAWN_RADIUS * f32::cos(theta),
-                0.5,
-                PLAYER_SPAWN_RADIUS * f32::sin(theta),
-            );
+        //scores and lives come from the server
+        let scores: Vec<(u8, u8)> = scoreboard
+            .iter()
+            .map(|(p, l)| (p.load(Ordering::Relaxed), l.load(Ordering::Relaxed)))
+            .collect();
+        let own_id = usize::from(player.player_id);
+        let myhealth = scores[own_id].1;
+        for (i, pe) in remote_players(&other_player_entities, player.player_id) {
+            let color = pe.material.color.extend(1.0);
+            let ((points, lives), (last_points, last_lives)) = (scores[i], last_scores[i]);
+            if points > last_points {
+                kill_feed.push(format!("Player {} reached the goal", i), color);
+            }
+            if lives < last_lives {
+                let message = if lives == 0 { "is out" } else { "fell off the map" };
+                kill_feed.push(format!("Player {} {}", i, message), color);
+            }
+        }
+        last_scores.clone_from(&scores);
+
+        //the server moves every player through the match phases together
+        let phase = match_state.phase();
+        let round = match_state.round.load(Ordering::Relaxed);
+        if round != last_round {
+            // rematch, on fresh terrain
+            last_round = round;
+            if let Err(e) = terrain.reset(&mut assets) {
+                println!("Terrain reset failed: {}", e);
+            }
+            minimap.update_heights(&terrain.ground);
+            respawn(&mut player, &mut rng);
+        }
+        if phase != last_phase {
+            last_phase = phase;
+            let yellow = vec4(1.0, 0.9, 0.3, 1.0);
+            match phase {
+                Phase::Playing => {
+                    kill_feed.push(String::from("Go!"), yellow);
+                    round_start = time::Instant::now();
+                    respawn(&mut player, &mut rng);
+                    if eliminated {
+                        eliminated = false;
+                        player.camera.set_mode(settings.camera_mode);
+                    }
+                }
+                Phase::Results => {
+                    let winner = match_state.winner.load(Ordering::Relaxed);
+                    kill_feed.push(result_text(winner, player.player_id), yellow);
+                }
+                _ => {}
+            }
+        }
+        if input.pressed(Action::Ready) && matches!(phase, Phase::Ready | Phase::Countdown) {
+            let mut m = Message::new(Command::READY);
+            m.push_bytes(player.player_id.as_bytes());
+            m.push_bytes((!match_state.is_ready(player.player_id) as u8).as_bytes());
+            listener.send(&m.get_bytes()).await?;
+        }
 
-            player.entity.set_pos(player_init_pos);
-            let old_camera = player.camera;
-            player.camera = camera::PlayerCamera::update(
-                player_init_pos,
-                scr_w as f32 / scr_h as f32,
-                theta2,
-                old_camera,
-            );
-            player.vec = vec3a(0.0, 0.0, 0.0);
+        //out of lives, watch the rest of the match
+        if phase == Phase::Playing && myhealth == 0 && !eliminated {
+            eliminated = true;
+            kill_feed.push(String::from("You are out of lives"), vec4(1.0, 0.4, 0.4, 1.0));
+            player.camera.set_mode(CameraMode::Spectator);
+        }
+
+        //respawn, goals and falls only count during a round and are sent to the server which keeps
+        //the score
+        if !eliminated && (has_goal || player.entity.pos.y < -5.0) {
+            respawn(&mut player, &mut rng);
+            if phase == Phase::Playing {
+                let mut m = Message::new(if has_goal { Command::GOAL } else { Command::DEATH });
+                m.push_bytes(player.player_id.as_bytes());
+                listener.send(&m.get_bytes()).await?;
+            }
             if has_goal {
-                if myscore == 9 {
-                    let _ = std::process::Command::new("target/release/image-ui")
-                        .args(["win"])
-                        .spawn();
-                    break;
-                }
-                myscore += 1;
-                let path = ["assets/mesh/", &myscore.to_string(), ".stl"].join("");
-                score_stl.mesh = Mesh::new(&path, vec3a(1.0, 1.0, 1.0));
-                music::play("assets/sounds/yay.mp3",&stream_handle);
+                kill_feed.push(String::from("You reached the goal"), vec4(1.0, 0.9, 0.3, 1.0));
+                audio.play(Sound::Goal);
             } else {
-                myhealth -= 1;
-                if myhealth == 0 {
-                    let _ = std::process::Command::new("target/release/image-ui")
-                        .args(["lose"])
-                        .spawn();
-                    break;
-                }
-                myhearts.pop();
-                let path = ["assets/mesh/", &myscore.to_string(), ".stl"].join("");
-                score_stl.mesh = Mesh::new(&path, vec3a(1.0, 1.0, 1.0));
-                music::play("assets/sounds/oof.mp3",&stream_handle);
+                kill_feed.push(String::from("You fell off the map"), vec4(1.0, 0.4, 0.4, 1.0));
+                audio.play(Sound::Fall);
             }
         }
 
@@ -608,77 +869,167 @@ async fn game(
 
        //move camera to player
         player.camera.player_pos = player.pos();
+        player.camera.update(player.vec, dt);
+        let moving = f32::abs(player.vec.x) > 0.0001 || f32::abs(player.vec.z) > 0.0001;
         player.camera.camera_angle +=
-            (if f32::abs(player.vec.x) > 0.0001 || f32::abs(player.vec.z) > 0.0001 {
+            // allows spin only if player vec is > 0, the spin keys strafe when spectating
+            (if moving && player.camera.mode != CameraMode::Spectator {
                 1
-            }
-            // allows spin only if player vec is > 0
-            else {
+            } else {
                 0
             }) as f32
-                * CAMERA_DELTA
-                * (keystates[1] - keystates[3]) as f32; // ks[1]-ks[3] as a & d keys - left/right
+                * CAMERA_SPIN_RATE
+                * dt
+                * input.axis(Action::SpinLeft, Action::SpinRight);
+        player.camera.camera_angle += CAMERA_SPIN_RATE * dt * input.axis(Action::TurnLeft, Action::TurnRight);
         
         //camera control
-        if x < scr_w as f64 * PAN_TRESHOLD_RATIO {
-            player.camera.camera_angle += CAMERA_DELTA;
+        if looking {
+            let invert = if settings.invert_y { -1.0 } else { 1.0 };
+            player.camera.camera_angle -= settings.mouse_sensitivity * look_delta.0 as f32;
+            player.camera.tilt += invert * settings.mouse_sensitivity * look_delta.1 as f32;
+        } else if x < scr_w as f64 * PAN_TRESHOLD_RATIO {
+            player.camera.camera_angle += CAMERA_SPIN_RATE * dt;
         } else if x > scr_w as f64 * (1.0 - PAN_TRESHOLD_RATIO) {
-            player.camera.camera_angle -= CAMERA_DELTA;
+            player.camera.camera_angle -= CAMERA_SPIN_RATE * dt;
         }
 
-        if y < scr_h as f64 * TILT_TRESHOLD_RATIO {
-            let (c, _) = player.camera.detect_col(&ground);
-            if !c && player.camera.eye().y > 0.2 {
-                player.camera.tilt -= CAMERA_DELTA;
-            }
-        } else if y > scr_h as f64 * (1.0 - TILT_TRESHOLD_RATIO) {
-            player.camera.tilt += CAMERA_DELTA;
-        }        
-
-        //collision detection for camera
-        player.camera.collide(&ground);
-
-        //score rotation
-        score_stl.mesh.rotate_y(0.03 * framenum as f32);
-        score_stl.pos = player.pos() + vec3a(0.0, 0.3, 0.0);
-    
-
-        //heart rotation
-        for heart in &mut myhearts {
-            heart.mesh.rotate_y(player.camera.camera_angle);
+        // no edge tilting while the cursor is captured
+        if !looking && y < scr_h as f64 * TILT_TRESHOLD_RATIO {
+            player.camera.tilt -= CAMERA_TILT_RATE * dt;
+        } else if !looking && y > scr_h as f64 * (1.0 - TILT_TRESHOLD_RATIO) {
+            player.camera.tilt += CAMERA_TILT_RATE * dt;
         }
+        player.camera.tilt -= CAMERA_TILT_RATE * dt * input.axis(Action::TiltUp, Action::TiltDown);
 
-        let offset = 0.13
-            * player
-            .camera
-            .up()
-            .cross(player.pos() - player.camera.eye())
-            .normalize();
+        //pull the camera in front of terrain hiding the player
+        player.camera.occlude(&[&terrain.ground], dt);
 
-        for i in 0..myhearts.len() {
-            myhearts[i].pos = player.pos() + vec3a(0.0, 0.5, 0.0) - offset * (i as f32 - 1.0);
+        //music, more tense on the last life
+        let cue = match (phase, myhealth) {
+            (Phase::Waiting | Phase::Ready, _) => Cue::Lobby,
+            (Phase::Results, _) => Cue::Victory,
+            (Phase::Playing, 1) => Cue::LastLife,
+            (Phase::Countdown | Phase::Playing, _) => Cue::Match,
+        };
+        music.set_cue(&audio, cue);
+        music.update(&audio, dt);
+
+        //positional audio, heard from the camera
+        audio.set_listener(player.camera.eye(), player.camera.right());
+        rumbles.update(&audio, dt);
+        let speed = player.vec.xz().length() / dt.max(f32::EPSILON);
+        let roll = if player.on_ground { (speed / ROLL_FULL_SPEED).min(1.0) } else { 0.0 };
+        audio.update_emitter(&own_roll, player.pos(), roll, 0.6 + 0.6 * roll);
+        for (i, pe) in remote_players(&other_player_entities, player.player_id) {
+            // positions arrive in bursts from the network, so the volume is smoothed
+            let (emitter, last_pos, roll) = &mut remote_rolls[i];
+            let speed = match last_pos {
+                Some(last) => (pe.pos - *last).xz().length() / dt.max(f32::EPSILON),
+                None => 0.0,
+            };
+            *roll += ((speed / ROLL_FULL_SPEED).min(1.0) - *roll) * (ROLL_SMOOTHING_RATE * dt).min(1.0);
+            audio.update_emitter(emitter, pe.pos, *roll, 0.6 + 0.6 * *roll);
+            *last_pos = Some(pe.pos);
+        }
+        // other players bumping into each other, played once as they touch
+        let remote: Vec<(usize, Vec3A)> = remote_players(&other_player_entities, player.player_id)
+            .map(|(i, pe)| (i, pe.pos))
+            .collect();
+        for (n, (i, a)) in remote.iter().enumerate() {
+            for (j, b) in &remote[n + 1..] {
+                let touching = a.distance(*b) < SPHERE_CONTACT_DISTANCE;
+                if touching && remote_contacts.insert((*i, *j)) {
+                    audio.play_at(Sound::Impact, 0.5 * (*a + *b), 0.6);
+                } else if !touching {
+                    remote_contacts.remove(&(*i, *j));
+                }
+            }
         }
-
 
         //draw players
+        let sky = map.sky(match_start.elapsed().as_secs_f32(), settings.day_night);
         unsafe {
+            gl::ClearColor(sky.clear_color.x, sky.clear_color.y, sky.clear_color.z, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
 
-            player
-                .entity
-                .draw(&mut player.camera, &lighting_program);
-
-            ground.draw(&mut player.camera, &lighting_program);
-
-            goal.draw(&mut player.camera, &lighting_program);
-            score_stl.draw(&mut player.camera, &lighting_program);
-            for e in &mut myhearts {
-                e.draw(&mut player.camera, &lighting_program);
-            }
-            for (pe, _score) in &mut other_player_entities {
-                pe.draw(&mut player.camera, &lighting_program);
-            }
+        for e in [&player.entity, &goal] {
+            let item = e.draw_item(&mut renderer, LIGHTING_PROGRAM);
+            renderer.submit(item);
         }
+        for item in terrain.draw_items(&mut renderer, LIGHTING_PROGRAM, player.camera.eye()) {
+            renderer.submit(item);
+        }
+        // remote players share one mesh, so they are a single instanced draw
+        for e in &other_player_entities {
+            let item = e.draw_item(&mut renderer, INSTANCED_PROGRAM);
+            renderer.submit(item);
+        }
+        renderer.render(&FrameUniforms {
+            proj: player.camera.proj_mat(),
+            view: player.camera.view_mat(),
+            view_pos: player.camera.eye(),
+            point_lights: map.point_lights.clone(),
+            sun_direction: sky.sun_direction,
+            sun_color: sky.sun_color,
+            light_space: settings.shadows.then(|| {
+                renderer::directional_light_space(sky.sun_direction, ORIGIN, shadow_radius)
+            }),
+            fog_color: sky.fog_color,
+            fog_density: map.fog_density,
+            skybox: Some(Skybox {
+                program: SKYBOX_PROGRAM,
+                cubemap: sky_cubemap,
+                tint: sky.sky_tint,
+            }),
+        });
+
+        //hud
+        if player.camera.mode != last_camera_mode {
+            last_camera_mode = player.camera.mode;
+            kill_feed.push(format!("Camera: {}", last_camera_mode), vec4(1.0, 1.0, 1.0, 1.0));
+        }
+        hud.begin(scr_w, scr_h);
+        draw_hud(
+            &mut hud,
+            player.camera.proj_mat() * player.camera.view_mat(),
+            player.player_id,
+            &other_player_entities,
+            &scores,
+            win_score,
+            if phase == Phase::Playing { round_start.elapsed() } else { time::Duration::ZERO },
+        );
+        let ready_key = input.bindings.get(Action::Ready).first().cloned().unwrap_or_default();
+        let players = num_players.load(Ordering::Relaxed);
+        draw_phase(&mut hud, &match_state, player.player_id, players, &ready_key);
+        if looking {
+            // crosshair over the point being edited
+            let center = vec2(0.5 * scr_w as f32, 0.5 * scr_h as f32);
+            let white = vec4(1.0, 1.0, 1.0, 0.8);
+            hud.rect(center - vec2(8.0, 1.0), vec2(16.0, 2.0), white);
+            hud.rect(center - vec2(1.0, 8.0), vec2(2.0, 16.0), white);
+        } else if pad_cursor.is_some() {
+            // the gamepad cursor has no system cursor to show it
+            let center = vec2(x as f32, y as f32);
+            let white = vec4(1.0, 1.0, 1.0, 0.8);
+            hud.rect(center - vec2(10.0, 1.0), vec2(6.0, 2.0), white);
+            hud.rect(center + vec2(4.0, -1.0), vec2(6.0, 2.0), white);
+            hud.rect(center - vec2(1.0, 10.0), vec2(2.0, 6.0), white);
+            hud.rect(center + vec2(-1.0, 4.0), vec2(2.0, 6.0), white);
+        }
+        kill_feed.draw(&mut hud, vec2(scr_w as f32 - HUD_MARGIN, HUD_MARGIN), HUD_SCALE);
+        let remote: Vec<(Vec3A, Vec3A)> = remote_players(&other_player_entities, player.player_id)
+            .map(|(_, pe)| (pe.pos, pe.material.color))
+            .collect();
+        minimap.draw(
+            &mut hud,
+            vec2(HUD_MARGIN, scr_h as f32 - HUD_MARGIN - MINIMAP_SIZE),
+            player.pos(),
+            player.camera.camera_angle,
+            &remote,
+        );
+        unsafe { hud.draw() }
 
         window.swap_buffers();
         tokio::time::sleep(DELTA_TIME).await;
@@ -688,7 +1039,131 @@ async fn game(
     Ok(())
 }
 
-fn cursor_raycast_and_animate(player: &mut Player, x: f64, scr_w: i32, y: f64, scr_h: i32, ground: &mut Entity, rt_marker: &mut Entity, window: &mut glfw::PWindow) -> usize {
+// Helper to build a shader program from assets/shaders/<name>.vs and <name>.fs, watching its
+// sources for hot reloading in dev mode
+unsafe fn load_program(
+    assets: &mut Assets,
+    name: &str,
+    defines: &[(&str, String)],
+) -> tokio::io::Result<ShaderProgram> {
+    let program = ShaderProgram::with_defines(
+        &assets.resolve(&format!("shaders/{}.vs", name))?,
+        &assets.resolve(&format!("shaders/{}.fs", name))?,
+        defines,
+    )?;
+    for source in &program.sources {
+        assets.watch(source);
+    }
+    Ok(program)
+}
+
+// Helper to get the map's skybox cubemap, or a gradient from the clear colour at the zenith to
+// the fog colour at the horizon so the sky meets the fogged terrain
+unsafe fn load_sky(assets: &mut Assets, map: &MapConfig) -> tokio::io::Result<assets::TextureHandle> {
+    Ok(match &map.skybox {
+        Some(name) => assets.cubemap(name)?,
+        None => assets.gradient_cubemap(map.clear_color, map.fog_color, 64),
+    })
+}
+
+// Helper to add the score, lives, match timer and remote player names to the HUD
+fn draw_hud(
+    hud: &mut Hud,
+    view_proj: glam::Mat4,
+    own_id: u8,
+    other_player_entities: &[Entity],
+    scores: &[(u8, u8)],
+    win_score: u8,
+    match_time: time::Duration,
+) {
+    let white = vec4(1.0, 1.0, 1.0, 1.0);
+    let margin = vec2(HUD_MARGIN, HUD_MARGIN);
+    let line_height = 10.0 * HUD_SCALE;
+    let (score, health) = scores[usize::from(own_id)];
+    hud.text(&format!("Score {}/{}", score, win_score), margin, HUD_SCALE, white);
+    let hearts = hud::HEART.to_string().repeat(health.into());
+    hud.text(&hearts, margin + vec2(0.0, line_height), HUD_SCALE, vec4(0.9, 0.2, 0.2, 1.0));
+
+    let seconds = match_time.as_secs();
+    let timer = format!("{}:{:02}", seconds / 60, seconds % 60);
+    hud.text_centered(&timer, vec2(0.5 * hud.screen().x, HUD_MARGIN), HUD_SCALE, white);
+
+    // names float above remote spheres
+    for (i, pe) in remote_players(other_player_entities, own_id) {
+        let above = pe.pos + vec3a(0.0, 0.3, 0.0);
+        if let Some(at) = hud::world_to_screen(view_proj, above, hud.screen()) {
+            let color = pe.material.color.extend(1.0);
+            let label = format!("Player {} - {}", i, scores[i].0);
+            hud.text_centered(&label, at, 0.5 * HUD_SCALE, color);
+        }
+    }
+}
+
+// Helper to add the match phase banner to the HUD, nothing is shown during a round
+fn draw_phase(hud: &mut Hud, match_state: &MatchState, own_id: u8, players: u8, ready_key: &str) {
+    let yellow = vec4(1.0, 0.9, 0.3, 1.0);
+    let white = vec4(1.0, 1.0, 1.0, 1.0);
+    let center = vec2(0.5 * hud.screen().x, 0.35 * hud.screen().y);
+    let below = center + vec2(0.0, 20.0 * HUD_SCALE);
+    let seconds = match_state.remaining_ms.load(Ordering::Relaxed).div_ceil(1000);
+    match match_state.phase() {
+        Phase::Waiting => hud.text_centered("Waiting for players", center, 2.0 * HUD_SCALE, yellow),
+        Phase::Ready | Phase::Countdown if !match_state.is_ready(own_id) => {
+            hud.text_centered(&format!("Press {} when ready", ready_key), center, 2.0 * HUD_SCALE, yellow);
+        }
+        Phase::Ready => {
+            let ready = match_state.ready.load(Ordering::Relaxed).count_ones();
+            hud.text_centered("Ready", center, 2.0 * HUD_SCALE, yellow);
+            hud.text_centered(&format!("{}/{} players ready", ready, players), below, HUD_SCALE, white);
+        }
+        Phase::Countdown => hud.text_centered(&seconds.to_string(), center, 4.0 * HUD_SCALE, yellow),
+        Phase::Playing => {}
+        Phase::Results => {
+            let winner = match_state.winner.load(Ordering::Relaxed);
+            hud.text_centered(&result_text(winner, own_id), center, 2.0 * HUD_SCALE, yellow);
+            hud.text_centered(&format!("Next round in {}", seconds), below, HUD_SCALE, white);
+        }
+    }
+}
+
+// Helper to put the player back at a random spot on the spawn circle
+fn respawn(player: &mut Player, rng: &mut ThreadRng) {
+    let theta = rng.gen_range(0.0..2.0 * PI);
+    let theta2 = rng.gen_range(0.0..2.0 * PI);
+    let player_init_pos = vec3a(
+        PLAYER_SPAWN_RADIUS * f32::cos(theta),
+        0.5,
+        PLAYER_SPAWN_RADIUS * f32::sin(theta),
+    );
+
+    player.entity.set_pos(player_init_pos);
+    player.camera.respawn(player_init_pos, theta2);
+    player.vec = vec3a(0.0, 0.0, 0.0);
+}
+
+// Helper to describe how the match ended
+fn result_text(winner: u8, own_id: u8) -> String {
+    match winner {
+        NO_PLAYER => String::from("Nobody wins"),
+        w if w == own_id => String::from("You win!"),
+        w => format!("Player {} wins", w),
+    }
+}
+
+// Helper to iterate the remote player spheres with their player ids
+// Skips our own slot and the hidden placeholder for the next player to join
+fn remote_players(
+    other_player_entities: &[Entity],
+    own_id: u8,
+) -> impl Iterator<Item = (usize, &Entity)> {
+    other_player_entities
+        .iter()
+        .enumerate()
+        .filter(move |(i, pe)| *i != usize::from(own_id) && pe.scale != Vec3A::ZERO)
+}
+
+fn cursor_raycast_and_animate(player: &mut Player, cursor: (f64, f64), screen: (i32, i32), ground: &mut Entity, rt_marker: &mut Entity, window: &mut glfw::PWindow) -> usize {
+    let ((x, y), (scr_w, scr_h)) = (cursor, screen);
     let mut raycast: usize = 0;
     let p = player.camera.proj_mat();
     let v = player.camera.view_mat();
@@ -707,7 +1182,7 @@ fn cursor_raycast_and_animate(player: &mut Player, x: f64, scr_w: i32, y: f64, s
     //set cursor animation
     if raydir.dot(vec3a(0.0, 1.0, 0.0)) < 0.0 {
         raycast = ground.closest_vertex_index(rt_marker.pos.xz());
-        let ground_y = ground.mesh.vertices[raycast].y;
+        let ground_y = ground.world_vertex(raycast).y;
         rt_marker.pos = eye - raydir * (eye.y - ground_y) / raydir.y;
         if rt_marker.pos.xz().distance(ORIGIN.xz()) < GROUND_IMMUTABLE_RADIUS {
             window.set_cursor(Some(Cursor::standard(Arrow)));
@@ -718,55 +1193,53 @@ fn cursor_raycast_and_animate(player: &mut Player, x: f64, scr_w: i32, y: f64, s
     raycast
 }
 
-fn handle_window_event(
+// Helper to apply the actions that happen once per press
+fn handle_actions(
     glfw: &mut glfw::Glfw,
     window: &mut glfw::Window,
-    event: glfw::WindowEvent,
+    input: &Input,
     player: &mut Player,
-    keystates: &mut [i8; 16],
+    mouse_look_toggled: &mut bool,
 ) {
-    match event {
-        glfw::WindowEvent::Key(glfw::Key::F, _, glfw::Action::Press, _) => {
-            let mut fullscreen = false;
-            window.with_window_mode(|mode| match mode {
-                glfw::WindowMode::Windowed => fullscreen = false,
-                glfw::WindowMode::FullScreen(_) => fullscreen = true,
+    if input.pressed(Action::Quit) {
+        window.set_should_close(true);
+    }
+    if input.pressed(Action::Fullscreen) {
+        let mut fullscreen = false;
+        window.with_window_mode(|mode| match mode {
+            glfw::WindowMode::Windowed => fullscreen = false,
+            glfw::WindowMode::FullScreen(_) => fullscreen = true,
+        });
+        if fullscreen {
+            window.set_monitor(glfw::WindowMode::Windowed, 0, 0, 800, 600, Some(60));
+        } else {
+            glfw.with_primary_monitor(|_, m| {
+                window.set_monitor(
+                    glfw::WindowMode::FullScreen(m.expect("Failed to set Fullscreen")),
+                    0,
+                    0,
+                    1920,
+                    1080,
+                    Some(60),
+                );
             });
-            if fullscreen {
-                window.set_monitor(glfw::WindowMode::Windowed, 0, 0, 800, 600, Some(60));
-            } else {
-                glfw.with_primary_monitor(|_, m| {
-                    window.set_monitor(
-                        glfw::WindowMode::FullScreen(m.expect("Failed to set Fullscreen")),
-                        0,
-                        0,
-                        1920,
-                        1080,
-                        Some(60),
-                    );
-                });
-            }
-        }
-        // jump
-        glfw::WindowEvent::Key(glfw::Key::Space, _, glfw::Action::Press, _) => {
-            if player.on_ground {
-                player.on_ground = false;
-                player.vec.y += 0.1;
-            }
         }
-        glfw::WindowEvent::Key(key, _, action, modifier) => {
-            keys::handle_key_event(window, key, action, modifier, keystates);
-        }
-
-        glfw::WindowEvent::MouseButton(mouse_button, action, modifier) => {
-            keys::handle_mouse_button(mouse_button, action, modifier, keystates);
-        }
-
-        glfw::WindowEvent::Scroll(_x, y) => {
-            player.camera.radius -= ZOOM_DELTA * y as f32;
-        }
-
-        _ => {}
+    }
+    if input.pressed(Action::CycleCamera) {
+        let next = player.camera.mode.next();
+        player.camera.set_mode(next);
+    }
+    if input.pressed(Action::ToggleMouseLook) {
+        *mouse_look_toggled = !*mouse_look_toggled;
+    }
+    // jump, the jump key may also fly the spectator camera up
+    if input.pressed(Action::Jump) && player.on_ground && player.camera.mode != CameraMode::Spectator {
+        player.on_ground = false;
+        player.vec.y += 0.1;
+    }
+    let zoom = input.presses(Action::ZoomIn) as f32 - input.presses(Action::ZoomOut) as f32;
+    if zoom != 0.0 {
+        player.camera.zoom(ZOOM_DELTA * zoom);
     }
 }
 
@@ -774,5 +1247,5 @@ fn randcolor(rng: &mut ThreadRng) -> Vec3A {
     let r = rng.gen_range(0.0..1.0);
     let g = rng.gen_range(0.0..1.0);
     let b = rng.gen_range(0.0..1.0);
-    return vec3a(r, g, b);
+    vec3a(r, g, b)
 }
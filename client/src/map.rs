@@ -0,0 +1,298 @@
+use crate::assets::{AssetError, Assets};
+use glam::{vec3a, Quat, Vec3, Vec3A};
+use std::f32::consts::PI;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const MAPS_DIR: &str = "maps";
+const MAP_EXTENSION: &str = "map";
+
+// Error returned when a map file cannot be read or parsed
+#[derive(Debug)]
+pub enum MapError {
+    Asset(AssetError),
+    Io { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, line: usize, reason: String },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Asset(e) => write!(f, "{}", e),
+            MapError::Io { path, source } => {
+                write!(f, "Map File: {} could not be read: {}", path.display(), source)
+            }
+            MapError::Parse { path, line, reason } => {
+                write!(f, "Map Parse Error ({}:{}): {}", path.display(), line, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<AssetError> for MapError {
+    fn from(e: AssetError) -> Self {
+        MapError::Asset(e)
+    }
+}
+
+// Allows map loading to be propagated with `?` inside the game loop
+impl From<MapError> for std::io::Error {
+    fn from(e: MapError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    }
+}
+
+// Point light, intensity falls off as 1 / (constant + linear * d + quadratic * d^2)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub attenuation: Vec3, // (constant, linear, quadratic), (1, 0, 0) for no falloff
+}
+
+// Animated day/night cycle, the sun turns a full circle every period
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DayNight {
+    pub period: f32, // seconds per day
+    pub night_sun_color: Vec3A,
+    pub night_clear_color: Vec3A,
+}
+
+// Sun, clear colour and sky state at some point of the day
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sky {
+    pub sun_direction: Vec3A, // normalized direction the sunlight travels
+    pub sun_color: Vec3A,
+    pub clear_color: Vec3A,
+    pub fog_color: Vec3A,
+    pub sky_tint: Vec3A, // multiplies the skybox, darkens it at night
+}
+
+// Per map settings, read from assets/maps/<name>.map
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapConfig {
+    pub path: PathBuf,
+    pub clear_color: Vec3A,
+    pub sun_direction: Vec3A, // direction the sunlight travels at the start of a match
+    pub sun_color: Vec3A,
+    pub point_lights: Vec<PointLight>,
+    pub day_night: Option<DayNight>,
+    pub fog_color: Vec3A,
+    pub fog_density: f32,        // exponential squared fog, 0.0 for none
+    pub skybox: Option<String>, // cubemap in assets/textures, None for a generated gradient
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        MapConfig {
+            path: PathBuf::new(),
+            clear_color: vec3a(0.52, 0.81, 0.92),
+            sun_direction: vec3a(-0.4, -1.0, -0.3).normalize(),
+            sun_color: vec3a(0.6, 0.6, 0.55),
+            point_lights: vec!(),
+            day_night: None,
+            fog_color: vec3a(0.52, 0.81, 0.92),
+            fog_density: 0.0,
+            skybox: None,
+        }
+    }
+}
+
+// MapConfig methods
+impl MapConfig {
+
+    // Map constructor from assets/maps/<name>.map
+    // Each line is a directive followed by whitespace separated numbers, # starts a comment:
+    //   clear_color r g b
+    //   sun_direction x y z
+    //   sun_color r g b
+    //   point x y z  r g b  [constant linear quadratic]
+    //   ring count radius height  r g b  [constant linear quadratic]
+    //   day_night period  night_sun_r g b  night_clear_r g b
+    //   fog r g b density
+    //   skybox name
+    // Lights past max_lights are dropped with a warning.
+    pub fn load(assets: &Assets, name: &str, max_lights: usize) -> Result<Self, MapError> {
+        let file = Path::new(MAPS_DIR).join(name).with_extension(MAP_EXTENSION);
+        let path = assets.resolve(&file.to_string_lossy())?;
+        let text = std::fs::read_to_string(&path).map_err(|source| MapError::Io {
+            path: path.clone(),
+            source,
+        })?;
+
+        let mut map = MapConfig {
+            path: path.clone(),
+            ..Default::default()
+        };
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut words = line.split_whitespace();
+            let Some(directive) = words.next() else {
+                continue;
+            };
+            let error = |reason: String| MapError::Parse {
+                path: path.clone(),
+                line: i + 1,
+                reason,
+            };
+            if directive == "skybox" {
+                let (Some(name), None) = (words.next(), words.next()) else {
+                    return Err(error(String::from("`skybox` takes one texture name")));
+                };
+                map.skybox = Some(String::from(name));
+                continue;
+            }
+            let args = words
+                .map(|w| w.parse::<f32>().map_err(|_| error(format!("invalid number `{}`", w))))
+                .collect::<Result<Vec<f32>, MapError>>()?;
+            let expect = |counts: &[usize]| {
+                if counts.contains(&args.len()) {
+                    Ok(())
+                } else {
+                    Err(error(format!("`{}` takes {:?} numbers, got {}", directive, counts, args.len())))
+                }
+            };
+            let v3 = |at: usize| vec3a(args[at], args[at + 1], args[at + 2]);
+            // lighting.fs divides by the attenuation, so it must be positive at every distance
+            let attenuation = |at: usize| {
+                if args.len() <= at {
+                    return Ok(Vec3::X);
+                }
+                let attenuation: Vec3 = v3(at).into();
+                if attenuation.min_element() < 0.0 || attenuation == Vec3::ZERO {
+                    return Err(error(String::from(
+                        "light attenuation must not be negative or all zero",
+                    )));
+                }
+                Ok(attenuation)
+            };
+
+            match directive {
+                "clear_color" => {
+                    expect(&[3])?;
+                    map.clear_color = v3(0);
+                }
+                "sun_direction" => {
+                    expect(&[3])?;
+                    map.sun_direction = v3(0).normalize_or_zero();
+                    if map.sun_direction == Vec3A::ZERO {
+                        return Err(error(String::from("sun direction must not be zero")));
+                    }
+                }
+                "sun_color" => {
+                    expect(&[3])?;
+                    map.sun_color = v3(0);
+                }
+                "point" => {
+                    expect(&[6, 9])?;
+                    map.point_lights.push(PointLight {
+                        position: v3(0).into(),
+                        color: v3(3).into(),
+                        attenuation: attenuation(6)?,
+                    });
+                }
+                "ring" => {
+                    expect(&[6, 9])?;
+                    if args[0] < 1.0 || args[0].fract() != 0.0 || args[0] > max_lights as f32 {
+                        return Err(error(format!(
+                            "ring light count must be a whole number from 1 to {}",
+                            max_lights
+                        )));
+                    }
+                    let count = args[0] as usize;
+                    let (radius, height) = (args[1], args[2]);
+                    let attenuation = attenuation(6)?;
+                    for n in 0..count {
+                        let theta = 2.0 * PI / (count as f32) * (n as f32);
+                        map.point_lights.push(PointLight {
+                            position: Vec3::new(radius * f32::cos(theta), height, radius * f32::sin(theta)),
+                            color: v3(3).into(),
+                            attenuation,
+                        });
+                    }
+                }
+                "day_night" => {
+                    expect(&[7])?;
+                    if args[0] <= 0.0 {
+                        return Err(error(String::from("day length must be positive")));
+                    }
+                    map.day_night = Some(DayNight {
+                        period: args[0],
+                        night_sun_color: v3(1),
+                        night_clear_color: v3(4),
+                    });
+                }
+                "fog" => {
+                    expect(&[4])?;
+                    map.fog_color = v3(0);
+                    map.fog_density = args[3].max(0.0);
+                }
+                _ => return Err(error(format!("unknown directive `{}`", directive))),
+            }
+        }
+
+        if map.point_lights.len() > max_lights {
+            println!(
+                "{} defines {} lights, only the first {} are used",
+                path.display(),
+                map.point_lights.len(),
+                max_lights
+            );
+            map.point_lights.truncate(max_lights);
+        }
+        Ok(map)
+    }
+
+    // Method to get the sun and clear colour `time` seconds into a match
+    // Without a day/night cycle (or with it disabled) this is the static map lighting. Otherwise
+    // the sun turns about the horizontal axis across its starting direction, fading to the
+    // night colours while it is below the horizon.
+    pub fn sky(&self, time: f32, day_night_enabled: bool) -> Sky {
+        let still = Sky {
+            sun_direction: self.sun_direction,
+            sun_color: self.sun_color,
+            clear_color: self.clear_color,
+            fog_color: self.fog_color,
+            sky_tint: Vec3A::ONE,
+        };
+        let Some(cycle) = self.day_night.filter(|_| day_night_enabled) else {
+            return still;
+        };
+
+        let horizontal = vec3a(self.sun_direction.x, 0.0, self.sun_direction.z).normalize_or_zero();
+        let axis = if horizontal == Vec3A::ZERO {
+            Vec3A::X
+        } else {
+            horizontal.cross(Vec3A::Y).normalize()
+        };
+        let angle = 2.0 * PI * (time / cycle.period).fract();
+        let sun_direction = (Quat::from_axis_angle(axis.into(), angle) * self.sun_direction).normalize();
+
+        // 0 at night, 1 once the sun is well above the horizon
+        let height = -sun_direction.y;
+        let t = ((height + 0.1) / 0.4).clamp(0.0, 1.0);
+        let day = t * t * (3.0 - 2.0 * t);
+        let clear_color = cycle.night_clear_color.lerp(self.clear_color, day);
+        Sky {
+            sun_direction,
+            sun_color: cycle.night_sun_color.lerp(self.sun_color, day),
+            clear_color,
+            fog_color: cycle.night_clear_color.lerp(self.fog_color, day),
+            sky_tint: clear_color / self.clear_color.max(Vec3A::splat(1e-3)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_map_fits_in_max_lights() {
+        let assets = Assets::with_root(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../assets"));
+        let map = MapConfig::load(&assets, crate::MAP_NAME, usize::MAX).unwrap();
+        assert!(map.point_lights.len() <= crate::MAX_LIGHTS);
+    }
+}
@@ -1,69 +1,259 @@
 extern crate stl_io;
 extern crate glam;
-use glam::vec3a;
+use crate::bounds::BoundingSphere;
+use glam::{vec2, vec3a, Mat4, Vec2, Vec3Swizzles};
 use glam::f32::{Vec3A,Mat3A};
-use stl_io::IndexedTriangle;
+use std::f32::consts::PI;
+use std::fmt;
 use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::rc::{Rc, Weak};
 
-// Mesh struct to store vertex and face and normals information
+const PLY_MAX_LIST_LEN: usize = 1024; // longest PLY list property (e.g. polygon) accepted
+
+// Error returned when a mesh file cannot be opened or parsed
 #[derive(Debug)]
+pub enum MeshError {
+    Io { path: String, source: std::io::Error },
+    Parse { path: String, reason: String },
+    UnsupportedFormat { path: String },
+    Empty { path: String },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::Io { path, source } => write!(f, "Mesh File: {} could not be read: {}", path, source),
+            MeshError::Parse { path, reason } => write!(f, "Mesh File: {} is malformed: {}", path, reason),
+            MeshError::UnsupportedFormat { path } => {
+                write!(f, "Mesh File: {} has an unsupported format (expected stl, obj, ply, gltf or glb)", path)
+            }
+            MeshError::Empty { path } => write!(f, "Mesh File: {} contains no triangles", path),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+// Allows mesh loading to be propagated with `?` inside the game loop
+impl From<MeshError> for std::io::Error {
+    fn from(e: MeshError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    }
+}
+
+// Triangle face, storing indices into Mesh::vertices and the face normal
+#[derive(Debug, Clone, Copy)]
+pub struct Face {
+    pub vertices: [usize; 3],
+    pub normal: Vec3A,
+}
+
+// Texture coordinate projection used when building vertices_normals_tex
+// UVs are always computed from the untransformed vertices, so they stay attached to the surface
+// when the mesh is rotated or deformed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UvMode {
+    Planar,    // project onto the xz plane (top down), suits the ground
+    Box,       // project onto the plane facing each triangle's dominant normal axis
+    Spherical, // longitude/latitude around the mesh centre, suits spheres
+    FromFile,  // use the texture coordinates stored in the file, planar if there are none
+}
+
+// Format independent mesh data returned by each file backend
+struct RawMesh {
+    positions: Vec<Vec3A>,
+    normals: Vec<Vec3A>, // per vertex, empty if the file has none
+    uvs: Vec<Vec2>,      // per vertex, empty if the file has none
+    indices: Vec<[usize; 3]>,
+    texture: Option<String>, // diffuse texture referenced by the file's material, if any
+}
+
+// Identity of a mesh, used by the renderer to find the GPU copy of a mesh across edits
+// Cloning a mesh gives the clone a new identity, so an edited copy never shares the original's upload
+#[derive(Debug)]
+pub struct MeshId(Rc<()>);
+
+impl MeshId {
+    // MeshId constructor
+    fn new() -> Self {
+        MeshId(Rc::new(()))
+    }
+
+    // Getter for a key that is unique while this mesh is alive
+    pub fn key(&self) -> *const () {
+        Rc::as_ptr(&self.0)
+    }
+
+    // Method to get a weak reference that can tell when the mesh has been dropped
+    pub fn downgrade(&self) -> Weak<()> {
+        Rc::downgrade(&self.0)
+    }
+}
+
+impl Clone for MeshId {
+    fn clone(&self) -> Self {
+        MeshId::new()
+    }
+}
+
+// Mesh struct to store vertex and face and normals information
+#[derive(Debug, Clone)]
 pub struct Mesh {
     pub path: String,
-    pub faces: Vec<IndexedTriangle>,
+    pub faces: Vec<Face>,
     pub vertices: Vec<Vec3A>,
+    pub normals: Vec<Vec3A>, // per vertex normals read from file, empty for flat shaded formats (stl)
+    pub uvs: Vec<Vec2>,      // per vertex texture coordinates read from file, empty if none
+    pub uv_mode: UvMode,
+    pub material_texture: Option<String>, // texture path from the file's material, if any
     pub vertices_normals_tex: Vec<Vec3A>, // New field for texture coordinates
+    pub revision: u64, // bumped whenever vertices_normals_tex changes, so GPU copies can be refreshed
+    pub bounds: BoundingSphere, // object space, kept up to date with mutations
+    deformed: bool, // set once mutated, file normals no longer match the surface
+    id: MeshId,
 }
 
 // Mesh methods
 impl Mesh {
-    
-    // Mesh constructor that takes stl path and scale as arguments
-    // Parses the stl file to extract vertex and face information and stores the scaled mesh into
-    // the Mesh struct. 
-    // vertices_normals_tex is used for shading.
-    pub fn new(path: &str, scale: Vec3A) -> Self {
-        let mut vertices = Vec::new();
-        let mut vertices_normals_tex = Vec::new(); // Initialize texture coordinates vector
 
-        let mut file = OpenOptions::new().read(true).open(path).unwrap();
-        let mesh = stl_io::read_stl(&mut file).unwrap();
+    // Mesh constructor that takes a mesh path and scale as arguments
+    // Dispatches on the file extension (stl, obj, ply, gltf/glb) to extract vertex and face
+    // information and stores the scaled mesh into the Mesh struct.
+    // vertices_normals_tex is used for shading.
+    pub fn new(path: &str, scale: Vec3A) -> Result<Self, MeshError> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
 
-        for v in &mesh.vertices {
-            let x = v[0] * scale[0];
-            let y = v[1] * scale[1];
-            let z = v[2] * scale[2];
-            vertices.push(vec3a(x,y,z));
+        let raw = match extension.as_deref() {
+            Some("stl") => load_stl(path)?,
+            Some("obj") => load_obj(path)?,
+            Some("ply") => load_ply(path)?,
+            Some("gltf") | Some("glb") => load_gltf(path)?,
+            _ => {
+                return Err(MeshError::UnsupportedFormat {
+                    path: String::from(path),
+                })
+            }
+        };
+        if raw.indices.is_empty() {
+            return Err(MeshError::Empty {
+                path: String::from(path),
+            });
         }
-
-        for face in &mesh.faces {
-            let n = vec3a(face.normal[0], face.normal[1], face.normal[2]);
-            for i in face.vertices {
-                let v = &mesh.vertices[i as usize];
-                vertices_normals_tex.push(
-                    vec3a(scale[0]*v[0], scale[1]*v[1], scale[2]*v[2])
-                );
-                vertices_normals_tex.push(n);
-                // Calculate texture coordinates based on vertex positions
-                let u = v[0]; // Example: Using x-coordinate as texture U coordinate
-                let v = v[2]; // Example: Using y-coordinate as texture V coordinate
-                vertices_normals_tex.push(vec3a(u, v, 0.0));
+        for face in &raw.indices {
+            if face.iter().any(|&i| i >= raw.positions.len()) {
+                return Err(MeshError::Parse {
+                    path: String::from(path),
+                    reason: String::from("face index out of range"),
+                });
             }
         }
-        
 
-        Mesh {
+        let vertices: Vec<Vec3A> = raw.positions.iter().map(|&v| v * scale).collect();
+
+        // Normals are transformed by the inverse scale to stay perpendicular to the surface
+        let normals = raw
+            .normals
+            .iter()
+            .map(|&n| (n / scale).normalize_or_zero())
+            .collect();
+
+        let faces = raw
+            .indices
+            .iter()
+            .map(|&f| Face {
+                vertices: f,
+                normal: face_normal(&vertices, f),
+            })
+            .collect();
+
+        let uv_mode = if raw.uvs.is_empty() {
+            UvMode::Planar
+        } else {
+            UvMode::FromFile
+        };
+
+        let mut mesh = Mesh {
             path: String::from(path),
-            faces: mesh.faces,
+            faces,
             vertices,
-            vertices_normals_tex, // Assign texture coordinates to the struct field
+            normals,
+            uvs: raw.uvs,
+            uv_mode,
+            material_texture: raw.texture,
+            vertices_normals_tex: vec!(),
+            revision: 0,
+            bounds: BoundingSphere::from_points(&[]), // set by rebuild
+            deformed: false,
+            id: MeshId::new(),
+        };
+        mesh.rebuild();
+        Ok(mesh)
+    }
+
+    // Method to build a mesh from a subset of this mesh's faces (e.g. a terrain chunk)
+    // Also returns, for each vertex of the new mesh, the index of the vertex it was copied from
+    pub fn submesh(&self, face_indices: &[usize]) -> (Mesh, Vec<usize>) {
+        let mut parent_of = vec!();
+        let mut local_of = std::collections::HashMap::new();
+        let mut faces = vec!();
+        for &f in face_indices {
+            let face = self.faces[f];
+            let vertices = face.vertices.map(|v| {
+                *local_of.entry(v).or_insert_with(|| {
+                    parent_of.push(v);
+                    parent_of.len() - 1
+                })
+            });
+            faces.push(Face {
+                vertices,
+                normal: face.normal,
+            });
         }
+        let pick = |data: &[Vec3A]| {
+            if data.len() == self.vertices.len() {
+                parent_of.iter().map(|&v| data[v]).collect()
+            } else {
+                vec!()
+            }
+        };
+        let mut mesh = Mesh {
+            path: self.path.clone(),
+            faces,
+            vertices: pick(&self.vertices),
+            normals: pick(&self.normals),
+            uvs: if self.uvs.len() == self.vertices.len() {
+                parent_of.iter().map(|&v| self.uvs[v]).collect()
+            } else {
+                vec!()
+            },
+            uv_mode: self.uv_mode,
+            material_texture: self.material_texture.clone(),
+            vertices_normals_tex: vec!(),
+            revision: 0,
+            bounds: self.bounds,
+            deformed: self.deformed,
+            id: MeshId::new(),
+        };
+        mesh.rebuild();
+        (mesh, parent_of)
+    }
+
+    // Setter for the texture coordinate projection, rebuilds the shading data
+    pub fn set_uv_mode(&mut self, uv_mode: UvMode) {
+        self.uv_mode = uv_mode;
+        self.rebuild();
     }
 
     // Method to flatten vertices_normals_tex into 1-dimension to be sent to shader
     pub fn vertices_flattened(&self) -> Vec<f32> {
         let mut v = vec!();
         for vertex in &self.vertices_normals_tex {
-            v.extend_from_slice(&mut vertex.to_array().as_slice());
+            v.extend_from_slice(vertex.to_array().as_slice());
         }
         v
     }
@@ -71,57 +261,657 @@ impl Mesh {
     // Method to mutate a single vertex of the mesh by some direction and amount
     pub fn mutate(&mut self, idx: usize, dir: Vec3A, amount: f32) {
         self.vertices[idx] += dir*amount;
-        self.vertices_normals_tex = vec!();
+        self.deformed = true;
+        self.rebuild();
+    }
+
+    // Helper to rebuild vertices_normals_tex (and face normals) from the vertex data.
+    // Face normals are used for shading once the mesh has been deformed.
+    // Rotation and scale are not baked in here, they are applied by the entity's model matrix.
+    fn rebuild(&mut self) {
+        let smooth = !self.deformed && self.normals.len() == self.vertices.len();
+        let center = self.center();
+
+        let mut vertices_normals_tex = vec!();
         for face in &mut self.faces {
-            let n: Vec3A;
-            let mut triangle_verts = [vec3a(0.0,0.0,0.0);3];
-            for i in 0..3 {
-                let v = &self.vertices[face.vertices[i] as usize];
-                triangle_verts[i] = vec3a(v[0], v[1], v[2]);
-            }
-            n = (triangle_verts[1]-triangle_verts[0]).cross(triangle_verts[2]-triangle_verts[0]).normalize();
-            face.normal = stl_io::Vector::new([n.x,n.y,n.z]);
+            let n = face_normal(&self.vertices, face.vertices);
+            face.normal = n;
             for i in face.vertices {
-                let v = &self.vertices[i as usize];
+                vertices_normals_tex.push(self.vertices[i]);
+                if smooth {
+                    vertices_normals_tex.push(self.normals[i]);
+                } else {
+                    vertices_normals_tex.push(n);
+                }
+                let uv = uv_at(self.uv_mode, &self.vertices, &self.uvs, i, n, center);
+                vertices_normals_tex.push(uv.extend(0.0).into());
+            }
+        }
+        self.vertices_normals_tex = vertices_normals_tex;
+        self.bounds = BoundingSphere::from_points(&self.vertices);
+        self.revision += 1;
+    }
 
-                self.vertices_normals_tex.push(
-                    vec3a(v[0], v[1], v[2])
-                );
-                self.vertices_normals_tex.push(n);
-                // Calculate texture coordinates based on vertex positions
-                let u = v[0]; // Example: Using x-coordinate as texture U coordinate
-                let v = v[2]; // Example: Using y-coordinate as texture V coordinate
-                self.vertices_normals_tex.push(vec3a(u, v, 0.0));
+    // Getter for whether the mesh has been mutated since it was loaded
+    pub fn deformed(&self) -> bool {
+        self.deformed
+    }
+
+    // Getter for the identity of this mesh, kept across edits
+    pub fn id(&self) -> &MeshId {
+        &self.id
+    }
+
+    // Method to get the centre of the mesh bounding box
+    pub fn center(&self) -> Vec3A {
+        let min = self.vertices.iter().fold(Vec3A::splat(f32::MAX), |a, &v| a.min(v));
+        let max = self.vertices.iter().fold(Vec3A::splat(f32::MIN), |a, &v| a.max(v));
+        0.5 * (min + max)
+    }
+}
+
+// Helper to calculate the texture coordinate of vertex i for a given projection,
+// n is the normal of the face being emitted
+fn uv_at(mode: UvMode, vertices: &[Vec3A], uvs: &[Vec2], i: usize, n: Vec3A, center: Vec3A) -> Vec2 {
+    let v = vertices[i];
+    match mode {
+        UvMode::FromFile if uvs.len() == vertices.len() => uvs[i],
+        UvMode::FromFile | UvMode::Planar => v.xz(),
+        UvMode::Box => {
+            let a = n.abs();
+            if a.x >= a.y && a.x >= a.z {
+                v.zy()
+            } else if a.y >= a.z {
+                v.xz()
+            } else {
+                v.xy()
             }
         }
+        UvMode::Spherical => {
+            let d = (v - center).normalize_or_zero();
+            vec2(
+                0.5 + f32::atan2(d.z, d.x) / (2.0 * PI),
+                0.5 + f32::asin(d.y.clamp(-1.0, 1.0)) / PI,
+            )
+        }
     }
+}
 
-    // Method to rotate the mesh about y-axis to allow animated spinning entities to be rendered
-    pub fn rotate_y(&mut self, theta: f32) {
-        let ry = Mat3A::from_rotation_y(theta);
+// Helper to compute the unit normal of a triangle from its winding order
+fn face_normal(vertices: &[Vec3A], f: [usize; 3]) -> Vec3A {
+    let (a, b, c) = (vertices[f[0]], vertices[f[1]], vertices[f[2]]);
+    (b - a).cross(c - a).normalize_or_zero()
+}
 
-        self.vertices_normals_tex = vec!();
-        for face in &mut self.faces {
-            let n: Vec3A;
-            let mut triangle_verts = [vec3a(0.0,0.0,0.0);3];
-            for i in 0..3 {
-                let v = &self.vertices[face.vertices[i] as usize];
-                triangle_verts[i] = ry*vec3a(v[0], v[1], v[2]);
-            }
-            n = (triangle_verts[1]-triangle_verts[0]).cross(triangle_verts[2]-triangle_verts[0]).normalize();
-            face.normal = stl_io::Vector::new([n.x,n.y,n.z]);
-            for i in face.vertices {
-                let v = &self.vertices[i as usize];
+// Helper to build a MeshError::Parse for a given path
+fn parse_error(path: &str, reason: impl ToString) -> MeshError {
+    MeshError::Parse {
+        path: String::from(path),
+        reason: reason.to_string(),
+    }
+}
+
+// Helper to open a mesh file, mapping failures into MeshError::Io
+fn open(path: &str) -> Result<std::fs::File, MeshError> {
+    OpenOptions::new().read(true).open(path).map_err(|source| MeshError::Io {
+        path: String::from(path),
+        source,
+    })
+}
+
+// STL backend: positions and face normals only, no texture coordinates
+fn load_stl(path: &str) -> Result<RawMesh, MeshError> {
+    let mut file = open(path)?;
+    let mesh = stl_io::read_stl(&mut file).map_err(|e| parse_error(path, e))?;
+    Ok(RawMesh {
+        positions: mesh.vertices.iter().map(|v| vec3a(v[0], v[1], v[2])).collect(),
+        normals: vec!(),
+        uvs: vec!(),
+        indices: mesh.faces.iter().map(|f| f.vertices).collect(),
+        texture: None,
+    })
+}
+
+// OBJ backend: every model in the file is merged into a single mesh
+fn load_obj(path: &str) -> Result<RawMesh, MeshError> {
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ..Default::default()
+    };
+    let (models, materials) = tobj::load_obj(path, &options).map_err(|e| match e {
+        tobj::LoadError::OpenFileFailed => MeshError::Io {
+            path: String::from(path),
+            source: std::io::Error::from(std::io::ErrorKind::NotFound),
+        },
+        e => parse_error(path, e),
+    })?;
+
+    let mut raw = RawMesh {
+        positions: vec!(),
+        normals: vec!(),
+        uvs: vec!(),
+        indices: vec!(),
+        texture: None,
+    };
+    let mut has_normals = true;
+    let mut has_uvs = true;
+    for model in &models {
+        let m = &model.mesh;
+        let offset = raw.positions.len();
+        let count = m.positions.len() / 3;
+        has_normals &= m.normals.len() == count * 3;
+        has_uvs &= m.texcoords.len() == count * 2;
+        for p in m.positions.chunks_exact(3) {
+            raw.positions.push(vec3a(p[0], p[1], p[2]));
+        }
+        for n in m.normals.chunks_exact(3) {
+            raw.normals.push(vec3a(n[0], n[1], n[2]));
+        }
+        for t in m.texcoords.chunks_exact(2) {
+            raw.uvs.push(vec2(t[0], t[1]));
+        }
+        for f in m.indices.chunks_exact(3) {
+            raw.indices.push([
+                offset + f[0] as usize,
+                offset + f[1] as usize,
+                offset + f[2] as usize,
+            ]);
+        }
+    }
+    if !has_normals {
+        raw.normals.clear();
+    }
+    if !has_uvs {
+        raw.uvs.clear();
+    }
+
+    // The first material with a diffuse map is used, paths are relative to the obj file
+    if let Ok(materials) = materials {
+        raw.texture = materials
+            .iter()
+            .find_map(|m| m.diffuse_texture.clone())
+            .map(|t| relative_to(path, &t));
+    }
+    Ok(raw)
+}
+
+// Helper to resolve a path referenced inside a mesh file against the mesh file's directory
+fn relative_to(mesh_path: &str, referenced: &str) -> String {
+    let dir = Path::new(mesh_path).parent().unwrap_or(Path::new(""));
+    dir.join(referenced).to_string_lossy().into_owned()
+}
+
+// glTF backend: walks the default scene, applying node transforms to each primitive
+fn load_gltf(path: &str) -> Result<RawMesh, MeshError> {
+    let (document, buffers, _images) = gltf::import(path).map_err(|e| match e {
+        gltf::Error::Io(source) => MeshError::Io {
+            path: String::from(path),
+            source,
+        },
+        e => parse_error(path, e),
+    })?;
+
+    let mut raw = RawMesh {
+        positions: vec!(),
+        normals: vec!(),
+        uvs: vec!(),
+        indices: vec!(),
+        texture: None,
+    };
+    let mut has_normals = true;
+    let mut has_uvs = true;
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| parse_error(path, "no scenes"))?;
+    let mut stack: Vec<(gltf::Node, Mat4)> = scene.nodes().map(|n| (n, Mat4::IDENTITY)).collect();
+
+    while let Some((node, parent)) = stack.pop() {
+        let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+        let normal_mat = Mat3A::from_mat4(transform).inverse().transpose();
+        for child in node.children() {
+            stack.push((child, transform));
+        }
+        let Some(mesh) = node.mesh() else { continue };
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+            // The first base colour texture stored as an external image is used
+            if raw.texture.is_none() {
+                if let Some(info) = primitive.material().pbr_metallic_roughness().base_color_texture() {
+                    if let gltf::image::Source::Uri { uri, .. } = info.texture().source().source() {
+                        raw.texture = Some(relative_to(path, uri));
+                    }
+                }
+            }
+            let reader = primitive.reader(|b| Some(&buffers[b.index()]));
+            let offset = raw.positions.len();
+            let Some(positions) = reader.read_positions() else { continue };
+            for p in positions {
+                raw.positions.push(transform.transform_point3a(Vec3A::from(p)));
+            }
+            let count = raw.positions.len() - offset;
+
+            match reader.read_normals() {
+                Some(normals) => {
+                    for n in normals {
+                        raw.normals.push((normal_mat * Vec3A::from(n)).normalize_or_zero());
+                    }
+                }
+                None => has_normals = false,
+            }
+            match reader.read_tex_coords(0) {
+                Some(uvs) => {
+                    for t in uvs.into_f32() {
+                        raw.uvs.push(Vec2::from(t));
+                    }
+                }
+                None => has_uvs = false,
+            }
+
+            let indices: Vec<usize> = match reader.read_indices() {
+                Some(i) => i.into_u32().map(|i| offset + i as usize).collect(),
+                None => (offset..offset + count).collect(),
+            };
+            for f in indices.chunks_exact(3) {
+                raw.indices.push([f[0], f[1], f[2]]);
+            }
+        }
+    }
+    if !has_normals || raw.normals.len() != raw.positions.len() {
+        raw.normals.clear();
+    }
+    if !has_uvs || raw.uvs.len() != raw.positions.len() {
+        raw.uvs.clear();
+    }
+    Ok(raw)
+}
 
-                self.vertices_normals_tex.push(
-                    ry*vec3a(v[0], v[1], v[2])
-                );
-                self.vertices_normals_tex.push(n);
-                // Calculate texture coordinates based on vertex positions
-                let u = v[0]; // Example: Using x-coordinate as texture U coordinate
-                let v = v[2]; // Example: Using y-coordinate as texture V coordinate
-                self.vertices_normals_tex.push(vec3a(u, v, 0.0));
+// Encodings a PLY body can be stored in
+#[derive(Clone, Copy, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+// A single PLY property, either a scalar or a list (count type, item type)
+enum PlyProperty {
+    Scalar(String, String),
+    List(String, String, String),
+}
+
+// A PLY element (vertex, face, ...) declared in the header
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+// PLY backend: supports ascii and binary encodings with x/y/z, nx/ny/nz, u/v (or s/t) vertex
+// properties and polygon faces, which are triangulated as fans
+fn load_ply(path: &str) -> Result<RawMesh, MeshError> {
+    let mut reader = BufReader::new(open(path)?);
+    let io_error = |source| MeshError::Io {
+        path: String::from(path),
+        source,
+    };
+
+    // header
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec!();
+    let mut line = String::new();
+    let mut first = true;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(io_error)? == 0 {
+            return Err(parse_error(path, "missing end_header"));
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if first {
+            if words != ["ply"] {
+                return Err(parse_error(path, "missing ply magic number"));
             }
+            first = false;
+            continue;
+        }
+        match words.as_slice() {
+            ["format", f, _] => {
+                format = Some(match *f {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(parse_error(path, format!("unknown format {}", f))),
+                });
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().map_err(|e| parse_error(path, e))?,
+                properties: vec!(),
+            }),
+            ["property", "list", count_ty, item_ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| parse_error(path, "property before element"))?
+                .properties
+                .push(PlyProperty::List(name.to_string(), count_ty.to_string(), item_ty.to_string())),
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| parse_error(path, "property before element"))?
+                .properties
+                .push(PlyProperty::Scalar(name.to_string(), ty.to_string())),
+            ["end_header"] => break,
+            _ => {} // comments, obj_info
+        }
+    }
+    let format = format.ok_or_else(|| parse_error(path, "missing format line"))?;
+
+    // body
+    let mut ascii_tokens: Vec<String> = vec!();
+    if format == PlyFormat::Ascii {
+        let mut body = String::new();
+        reader.read_to_string(&mut body).map_err(io_error)?;
+        ascii_tokens = body.split_whitespace().rev().map(String::from).collect();
+    }
+    let mut read_value = |ty: &str| -> Result<f64, MeshError> {
+        if format == PlyFormat::Ascii {
+            let token = ascii_tokens.pop().ok_or_else(|| parse_error(path, "unexpected end of file"))?;
+            return token.parse::<f64>().map_err(|e| parse_error(path, e));
+        }
+        let size = match ty {
+            "char" | "uchar" | "int8" | "uint8" => 1,
+            "short" | "ushort" | "int16" | "uint16" => 2,
+            "int" | "uint" | "float" | "int32" | "uint32" | "float32" => 4,
+            "double" | "float64" => 8,
+            _ => return Err(parse_error(path, format!("unknown property type {}", ty))),
+        };
+        let mut b = [0u8; 8];
+        reader.read_exact(&mut b[..size]).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => parse_error(path, "unexpected end of file"),
+            _ => io_error(e),
+        })?;
+        if format == PlyFormat::BinaryBigEndian {
+            b[..size].reverse();
+        }
+        Ok(match ty {
+            "char" | "int8" => b[0] as i8 as f64,
+            "uchar" | "uint8" => b[0] as f64,
+            "short" | "int16" => i16::from_le_bytes([b[0], b[1]]) as f64,
+            "ushort" | "uint16" => u16::from_le_bytes([b[0], b[1]]) as f64,
+            "int" | "int32" => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            "uint" | "uint32" => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            "float" | "float32" => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            _ => f64::from_le_bytes(b),
+        })
+    };
+
+    let mut raw = RawMesh {
+        positions: vec!(),
+        normals: vec!(),
+        uvs: vec!(),
+        indices: vec!(),
+        texture: None,
+    };
+    for element in &elements {
+        let names: Vec<&str> = element
+            .properties
+            .iter()
+            .map(|p| match p {
+                PlyProperty::Scalar(n, _) | PlyProperty::List(n, _, _) => n.as_str(),
+            })
+            .collect();
+        let has = |n: &[&str]| n.iter().all(|n| names.contains(n));
+        let has_normals = has(&["nx", "ny", "nz"]);
+        let uv_names = if has(&["u", "v"]) {
+            Some(["u", "v"])
+        } else if has(&["s", "t"]) {
+            Some(["s", "t"])
+        } else if has(&["texture_u", "texture_v"]) {
+            Some(["texture_u", "texture_v"])
+        } else {
+            None
+        };
+
+        for _ in 0..element.count {
+            let mut scalars: Vec<(&str, f64)> = vec!();
+            let mut lists: Vec<(&str, Vec<usize>)> = vec!();
+            for property in &element.properties {
+                match property {
+                    PlyProperty::Scalar(name, ty) => scalars.push((name, read_value(ty)?)),
+                    PlyProperty::List(name, count_ty, item_ty) => {
+                        // the count comes straight from the file, so it is checked before reserving
+                        let count = read_value(count_ty)?;
+                        if !(0.0..=PLY_MAX_LIST_LEN as f64).contains(&count) || count.fract() != 0.0 {
+                            return Err(parse_error(path, format!("invalid list length {}", count)));
+                        }
+                        let count = count as usize;
+                        let mut items = Vec::with_capacity(count);
+                        for _ in 0..count {
+                            items.push(read_value(item_ty)? as usize);
+                        }
+                        lists.push((name, items));
+                    }
+                }
+            }
+            let get = |n: &str| {
+                scalars.iter().find(|(name, _)| *name == n).map_or(0.0, |(_, v)| *v as f32)
+            };
+            match element.name.as_str() {
+                "vertex" => {
+                    raw.positions.push(vec3a(get("x"), get("y"), get("z")));
+                    if has_normals {
+                        raw.normals.push(vec3a(get("nx"), get("ny"), get("nz")));
+                    }
+                    if let Some([u, v]) = uv_names {
+                        raw.uvs.push(vec2(get(u), get(v)));
+                    }
+                }
+                "face" => {
+                    for (name, polygon) in &lists {
+                        if *name == "vertex_indices" || *name == "vertex_index" {
+                            for i in 1..polygon.len().saturating_sub(1) {
+                                raw.indices.push([polygon[0], polygon[i], polygon[i + 1]]);
+                            }
+                        }
+                    }
+                }
+                _ => {} // edges, materials etc. are ignored
+            }
+        }
+    }
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Helper to load a mesh from an inline buffer, written to a temporary file named `name`
+    fn load(name: &str, contents: &[u8]) -> Result<Mesh, MeshError> {
+        let path = std::env::temp_dir().join(format!("sephere-mesh-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        let mesh = Mesh::new(&path.to_string_lossy(), Vec3A::ONE);
+        std::fs::remove_file(&path).unwrap();
+        mesh
+    }
+
+    // Helper to check that loading failed because the file is malformed
+    fn assert_parse_error(result: Result<Mesh, MeshError>) {
+        match result {
+            Err(MeshError::Parse { .. }) => {}
+            other => panic!("expected a parse error, got {:?}", other.map(|m| m.faces.len())),
+        }
+    }
+
+    // Helper to get the texture coordinates of every face vertex, in draw order
+    fn uvs(mesh: &Mesh) -> Vec<Vec2> {
+        mesh.vertices_normals_tex.chunks(3).map(|v| v[2].xy()).collect()
+    }
+
+    const ASCII_STL: &str = "solid t\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\n\
+                             endloop\nendfacet\nendsolid t\n";
+
+    #[test]
+    fn loads_ascii_stl() {
+        let mesh = load("ascii.stl", ASCII_STL.as_bytes()).unwrap();
+        assert_eq!(mesh.faces.len(), 1);
+        assert_eq!(mesh.vertices.len(), 3);
+        assert!(mesh.normals.is_empty());
+        assert_eq!(mesh.faces[0].normal, Vec3A::Z);
+    }
+
+    #[test]
+    fn uv_modes_project_the_vertices() {
+        // a triangle in the xy plane, facing +z
+        let mut mesh = load("uv.stl", ASCII_STL.as_bytes()).unwrap();
+        assert_eq!(mesh.uv_mode, UvMode::Planar);
+        assert_eq!(uvs(&mesh), [Vec2::ZERO, Vec2::X, Vec2::ZERO]);
+
+        mesh.set_uv_mode(UvMode::Box);
+        assert_eq!(uvs(&mesh), [Vec2::ZERO, Vec2::X, Vec2::Y]);
+
+        // around the bounding box centre (0.5, 0.5, 0)
+        mesh.set_uv_mode(UvMode::Spherical);
+        let spherical = uvs(&mesh);
+        assert!(spherical[1].abs_diff_eq(vec2(0.5, 0.25), 1e-6));
+        assert!(spherical[2].abs_diff_eq(vec2(1.0, 0.75), 1e-6));
+
+        // stl has no texture coordinates, so this falls back to planar
+        mesh.set_uv_mode(UvMode::FromFile);
+        assert_eq!(uvs(&mesh), [Vec2::ZERO, Vec2::X, Vec2::ZERO]);
+    }
+
+    #[test]
+    fn from_file_uvs_come_from_the_file() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 0 1\nvt 0.1 0.2\nvt 0.3 0.4\nvt 0.5 0.6\nf 1/1 2/2 3/3\n";
+        let mesh = load("uv.obj", obj.as_bytes()).unwrap();
+        assert_eq!(mesh.uv_mode, UvMode::FromFile);
+        assert_eq!(uvs(&mesh), [vec2(0.1, 0.2), vec2(0.3, 0.4), vec2(0.5, 0.6)]);
+    }
+
+    #[test]
+    fn uv_mode_survives_mutation() {
+        let mut mesh = load("uv-mutate.stl", ASCII_STL.as_bytes()).unwrap();
+        mesh.set_uv_mode(UvMode::Box);
+        mesh.mutate(1, Vec3A::X, 1.0);
+        assert_eq!(mesh.uv_mode, UvMode::Box);
+        assert_eq!(uvs(&mesh), [Vec2::ZERO, vec2(2.0, 0.0), Vec2::Y]);
+    }
+
+    #[test]
+    fn rejects_truncated_binary_stl() {
+        let mut stl = vec![0u8; 80];
+        stl.extend_from_slice(&2u32.to_le_bytes()); // two triangles promised, half of one given
+        stl.extend_from_slice(&[0u8; 25]);
+        assert!(load("truncated.stl", &stl).is_err());
+    }
+
+    #[test]
+    fn loads_obj_with_normals_and_uvs() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 0 1\nv 0 0 1\n\
+                   vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 1 0\n\
+                   f 1/1/1 2/2/1 3/3/1 4/4/1\n";
+        let mesh = load("quad.obj", obj.as_bytes()).unwrap();
+        assert_eq!(mesh.faces.len(), 2); // triangulated
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.normals, vec![Vec3A::Y; 4]);
+        assert_eq!(mesh.uvs.len(), 4);
+        assert_eq!(mesh.uv_mode, UvMode::FromFile);
+    }
+
+    #[test]
+    fn rejects_obj_faces_outside_the_vertices() {
+        assert!(load("bad_index.obj", b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 9\n").is_err());
+    }
+
+    #[test]
+    fn loads_ascii_ply_polygons_as_fans() {
+        let ply = "ply\nformat ascii 1.0\ncomment a quad\nelement vertex 4\n\
+                   property float x\nproperty float y\nproperty float z\n\
+                   property float nx\nproperty float ny\nproperty float nz\n\
+                   element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+                   0 0 0 0 1 0\n1 0 0 0 1 0\n1 0 1 0 1 0\n0 0 1 0 1 0\n4 0 1 2 3\n";
+        let mesh = load("quad.ply", ply.as_bytes()).unwrap();
+        assert_eq!(mesh.faces.iter().map(|f| f.vertices).collect::<Vec<_>>(), [[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.normals, vec![Vec3A::Y; 4]);
+    }
+
+    #[test]
+    fn loads_binary_ply() {
+        let mut ply = b"ply\nformat binary_little_endian 1.0\nelement vertex 3\n\
+                        property float x\nproperty float y\nproperty float z\n\
+                        element face 1\nproperty list uchar int vertex_indices\nend_header\n"
+            .to_vec();
+        for v in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            ply.extend_from_slice(&v.to_le_bytes());
+        }
+        ply.push(3);
+        for i in [0i32, 1, 2] {
+            ply.extend_from_slice(&i.to_le_bytes());
         }
+        let mesh = load("triangle.ply", &ply).unwrap();
+        assert_eq!(mesh.vertices, [Vec3A::ZERO, Vec3A::X, Vec3A::Y]);
+        assert_eq!(mesh.faces.len(), 1);
+    }
+
+    #[test]
+    fn rejects_malformed_ply() {
+        assert_parse_error(load("magic.ply", b"plyx\nformat ascii 1.0\nend_header\n"));
+        assert_parse_error(load("no_end.ply", b"ply\nformat ascii 1.0\nelement vertex 3\n"));
+        assert_parse_error(load(
+            "truncated.ply",
+            b"ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+              property float z\nend_header\n0 0 0\n1 0\n",
+        ));
+        let mut truncated = b"ply\nformat binary_big_endian 1.0\nelement vertex 3\nproperty float x\n\
+                              property float y\nproperty float z\nend_header\n"
+            .to_vec();
+        truncated.extend_from_slice(&[0u8; 10]);
+        assert_parse_error(load("truncated_binary.ply", &truncated));
+    }
+
+    #[test]
+    fn rejects_ply_lists_longer_than_the_limit() {
+        let mut ply = b"ply\nformat binary_little_endian 1.0\nelement face 1\n\
+                        property list uint int vertex_indices\nend_header\n"
+            .to_vec();
+        ply.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert_parse_error(load("huge_list.ply", &ply));
+    }
+
+    #[test]
+    fn loads_gltf_with_node_transforms() {
+        let gltf = r#"{
+            "asset": {"version": "2.0"},
+            "scene": 0,
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0, "translation": [0, 2, 0]}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}}]}],
+            "buffers": [{
+                "byteLength": 36,
+                "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA"
+            }],
+            "bufferViews": [{"buffer": 0, "byteLength": 36}],
+            "accessors": [{
+                "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [0, 0, 0], "max": [1, 1, 0]
+            }]
+        }"#;
+        let mesh = load("triangle.gltf", gltf.as_bytes()).unwrap();
+        assert_eq!(mesh.vertices, [2.0 * Vec3A::Y, vec3a(1.0, 2.0, 0.0), 3.0 * Vec3A::Y]);
+        assert_eq!(mesh.faces.len(), 1);
+    }
+
+    #[test]
+    fn rejects_malformed_gltf() {
+        assert_parse_error(load("broken.gltf", b"{\"asset\": {\"version\": \"2.0\"}, \"scenes\": ["));
+    }
+
+    #[test]
+    fn rejects_unknown_extensions() {
+        assert!(matches!(load("mesh.fbx", b""), Err(MeshError::UnsupportedFormat { .. })));
     }
 }
@@ -0,0 +1,128 @@
+use crate::entities::Entity;
+use crate::hud::Hud;
+use glam::{vec2, vec4, Vec2, Vec3A, Vec3Swizzles};
+use std::f32::consts::PI;
+
+const CELLS: usize = 32; // per side
+const HEIGHT_RANGE: f32 = 2.0; // heights this far above or below zero get the lightest or darkest shade
+const MARKER_SIZE: f32 = 8.0;
+const RING_DOTS: usize = 24;
+
+// Top-down map of the ground heights drawn on the HUD, north (-z) is up
+pub struct Minimap {
+    pub size: f32, // side length in screen pixels
+    min: Vec2,     // world xz of the top left corner
+    extent: f32,   // world size of the mapped square
+    heights: Vec<Option<f32>>, // mean ground height per cell, None where there is no ground
+    base_color: Vec3A,
+    pub goal: Vec3A,
+    pub immutable_radius: f32, // ground this close to the goal can't be changed
+}
+
+// Minimap methods
+impl Minimap {
+
+    // Minimap constructor covering the whole ground
+    pub fn new(ground: &Entity, size: f32, goal: Vec3A, immutable_radius: f32) -> Self {
+        let mut minimap = Minimap {
+            size,
+            min: Vec2::ZERO,
+            extent: 1.0,
+            heights: vec!(),
+            base_color: ground.material.color,
+            goal,
+            immutable_radius,
+        };
+        minimap.update_heights(ground);
+        minimap
+    }
+
+    // Method to resample the ground heights, called whenever the ground is mutated or reloaded
+    pub fn update_heights(&mut self, ground: &Entity) {
+        let points: Vec<Vec3A> = (0..ground.mesh.vertices.len()).map(|i| ground.world_vertex(i)).collect();
+        let min = points.iter().fold(Vec2::splat(f32::MAX), |a, v| a.min(v.xz()));
+        let max = points.iter().fold(Vec2::splat(f32::MIN), |a, v| a.max(v.xz()));
+        // square around the ground so the map is not stretched
+        self.extent = (max - min).max_element().max(f32::EPSILON);
+        self.min = 0.5 * (min + max) - Vec2::splat(0.5 * self.extent);
+        self.base_color = ground.material.color;
+
+        let mut sums = vec![(0.0, 0u32); CELLS * CELLS];
+        for p in &points {
+            let cell = self.cell(p.xz());
+            sums[cell].0 += p.y;
+            sums[cell].1 += 1;
+        }
+        self.heights = sums
+            .into_iter()
+            .map(|(sum, count)| (count > 0).then(|| sum / count as f32))
+            .collect();
+    }
+
+    // Helper to get the cell index of a world xz position, clamped to the map
+    fn cell(&self, xz: Vec2) -> usize {
+        let at = ((xz - self.min) / self.extent * CELLS as f32).floor();
+        let (x, z) = (
+            (at.x.max(0.0) as usize).min(CELLS - 1),
+            (at.y.max(0.0) as usize).min(CELLS - 1),
+        );
+        z * CELLS + x
+    }
+
+    // Helper to get the screen position of a world position, clamped to the map edge
+    fn to_screen(&self, pos: Vec3A, top_left: Vec2) -> Vec2 {
+        let t = ((pos.xz() - self.min) / self.extent).clamp(Vec2::ZERO, Vec2::ONE);
+        top_left + t * self.size
+    }
+
+    // Method to add the map to the HUD
+    // `heading` is the camera angle, `players` are the positions and colours of remote players
+    pub fn draw(
+        &self,
+        hud: &mut Hud,
+        top_left: Vec2,
+        own_pos: Vec3A,
+        heading: f32,
+        players: &[(Vec3A, Vec3A)],
+    ) {
+        hud.rect(top_left - Vec2::splat(4.0), Vec2::splat(self.size + 8.0), vec4(0.0, 0.0, 0.0, 0.5));
+
+        // terrain, raised ground is lighter and dug out ground darker
+        let cell_size = self.size / CELLS as f32;
+        for (i, height) in self.heights.iter().enumerate() {
+            let Some(height) = height else {
+                continue;
+            };
+            let t = 0.5 * ((height / HEIGHT_RANGE).clamp(-1.0, 1.0) + 1.0);
+            let color = (self.base_color * (0.4 + 0.9 * t)).min(Vec3A::ONE);
+            let at = top_left + cell_size * vec2((i % CELLS) as f32, (i / CELLS) as f32);
+            hud.rect(at, Vec2::splat(cell_size), color.extend(1.0));
+        }
+
+        // ring of dots around the goal where the ground can't be changed
+        for n in 0..RING_DOTS {
+            let theta = 2.0 * PI / RING_DOTS as f32 * n as f32;
+            let at = self.goal + self.immutable_radius * Vec3A::new(f32::cos(theta), 0.0, f32::sin(theta));
+            let center = self.to_screen(at, top_left);
+            hud.rect(center - Vec2::splat(1.0), Vec2::splat(2.0), vec4(1.0, 1.0, 1.0, 0.8));
+        }
+        let center = self.to_screen(self.goal, top_left);
+        hud.rect(center - Vec2::splat(0.5 * MARKER_SIZE), Vec2::splat(MARKER_SIZE), vec4(0.8, 0.8, 0.2, 1.0));
+
+        for (pos, color) in players {
+            let center = self.to_screen(*pos, top_left);
+            let half = Vec2::splat(0.5 * MARKER_SIZE);
+            hud.rect(center - half - Vec2::ONE, Vec2::splat(MARKER_SIZE + 2.0), vec4(0.0, 0.0, 0.0, 1.0));
+            hud.rect(center - half, Vec2::splat(MARKER_SIZE), color.extend(1.0));
+        }
+
+        // own position as an arrow along the direction forward moves in
+        let center = self.to_screen(own_pos, top_left);
+        let forward = vec2(-f32::sin(heading), -f32::cos(heading));
+        let side = forward.perp();
+        let tip = center + MARKER_SIZE * forward;
+        let back = center - 0.6 * MARKER_SIZE * forward;
+        let white = vec4(1.0, 1.0, 1.0, 1.0);
+        hud.triangle([tip, back + 0.6 * MARKER_SIZE * side, back - 0.6 * MARKER_SIZE * side], white);
+    }
+}
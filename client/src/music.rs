@@ -1,19 +1,184 @@
-use rodio::OutputStreamHandle;
-use rodio::{source::Source, Decoder};
+use crate::assets::Assets;
+use crate::audio::Audio;
+use rand::seq::SliceRandom;
+use rodio::{Decoder, Sink};
 use std::fs::File;
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-pub async fn main() {}
+const MUSIC_DIR: &str = "music";
+const EXTENSIONS: [&str; 4] = ["mp3", "ogg", "wav", "flac"];
+const CROSSFADE: f32 = 3.0; // seconds
 
-pub fn play(path: &str, stream_handle: &OutputStreamHandle) {
-    // Load a sound from a file, using a path relative to Cargo.toml
-    let file = BufReader::new(File::open(path).unwrap());
-    // Decode that sound file into a source
-    let source = Decoder::new(file).unwrap();
-    // Play the sound directly on the device
-    let _ = stream_handle.play_raw(source.convert_samples());
+// Part of the game the music is for, each has its own playlist
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cue {
+    Lobby,
+    Match,
+    LastLife, // down to the last life
+    Victory,
+}
+
+impl Cue {
+    pub const ALL: [Cue; 4] = [Cue::Lobby, Cue::Match, Cue::LastLife, Cue::Victory];
+
+    // Getter for the cue's folder in assets/music
+    pub fn folder(self) -> &'static str {
+        match self {
+            Cue::Lobby => "lobby",
+            Cue::Match => "match",
+            Cue::LastLife => "last_life",
+            Cue::Victory => "victory",
+        }
+    }
+}
+
+// Track being streamed from its file
+struct Track {
+    sink: Sink,
+    fade: f32,               // from 0 to 1
+    ends: Option<Instant>,   // when the file says how long it is
+}
+
+// Music player, streams the current cue's playlist and crossfades between tracks and cues
+// Without an audio device or music files it stays silent
+pub struct MusicPlayer {
+    playlists: Vec<Vec<PathBuf>>, // indexed like Cue::ALL
+    positions: Vec<usize>,        // next track of each playlist
+    cue: Option<Cue>,
+    current: Option<Track>,
+    fading: Vec<Track>, // tracks fading out
+}
+
+impl MusicPlayer {
+    // MusicPlayer constructor, finds the tracks of every cue in assets/music
+    // Each cue plays the files in its own folder, or the files directly in assets/music if it has none
+    pub fn new(assets: &Assets) -> Self {
+        let Ok(dir) = assets.resolve(MUSIC_DIR) else {
+            return MusicPlayer::with_playlists(vec![vec!(); Cue::ALL.len()]);
+        };
+        let shared = tracks_in(&dir);
+        let playlists = Cue::ALL
+            .iter()
+            .map(|cue| {
+                let tracks = tracks_in(&dir.join(cue.folder()));
+                if tracks.is_empty() { shared.clone() } else { tracks }
+            })
+            .collect();
+        MusicPlayer::with_playlists(playlists)
+    }
+
+    // Helper to build a player that hasn't started, with the playlists shuffled
+    fn with_playlists(mut playlists: Vec<Vec<PathBuf>>) -> Self {
+        let mut rng = rand::thread_rng();
+        for playlist in &mut playlists {
+            playlist.shuffle(&mut rng);
+        }
+        MusicPlayer {
+            positions: vec![0; playlists.len()],
+            playlists,
+            cue: None,
+            current: None,
+            fading: vec!(),
+        }
+    }
+
+    // Getter for the cue being played
+    pub fn cue(&self) -> Option<Cue> {
+        self.cue
+    }
+
+    // Method to switch to another cue's playlist, crossfading from the current track
+    pub fn set_cue(&mut self, audio: &Audio, cue: Cue) {
+        if self.cue == Some(cue) {
+            return;
+        }
+        self.cue = Some(cue);
+        self.start_next(audio);
+    }
+
+    // Method to fade the tracks and move on through the playlist, called every frame
+    pub fn update(&mut self, audio: &Audio, dt: f32) {
+        let step = dt / CROSSFADE;
+        let volume = audio.volume.music();
+        if let Some(track) = &mut self.current {
+            track.fade = (track.fade + step).min(1.0);
+            track.sink.set_volume(track.fade * volume);
+        }
+        for track in &mut self.fading {
+            track.fade = (track.fade - step).max(0.0);
+            track.sink.set_volume(track.fade * volume);
+        }
+        self.fading.retain(|t| t.fade > 0.0 && !t.sink.empty());
+
+        // the next track fades in as the current one fades out, or once it has finished when its
+        // length isn't known
+        let finished = match &self.current {
+            Some(track) => {
+                track.sink.empty() || track.ends.is_some_and(|ends| Instant::now() >= ends)
+            }
+            None => false,
+        };
+        if finished {
+            self.start_next(audio);
+        }
+    }
+
+    // Helper to fade out the current track and start the next one of the cue's playlist
+    // Tracks that can't be played are skipped
+    fn start_next(&mut self, audio: &Audio) {
+        if let Some(track) = self.current.take() {
+            self.fading.push(track);
+        }
+        let (Some(cue), Some(handle)) = (self.cue, audio.handle()) else {
+            return;
+        };
+        let playlist = &self.playlists[cue as usize];
+        for _ in 0..playlist.len() {
+            let position = &mut self.positions[cue as usize];
+            let path = &playlist[*position % playlist.len()];
+            *position = (*position + 1) % playlist.len();
+            match open_track(path, handle) {
+                Ok(track) => {
+                    self.current = Some(track);
+                    return;
+                }
+                Err(e) => println!("Could not play {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+// Helper to start streaming a music file, silent until it fades in
+fn open_track(path: &Path, handle: &rodio::OutputStreamHandle) -> Result<Track, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let source = Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+    let length = rodio::Source::total_duration(&source);
+    let sink = Sink::try_new(handle).map_err(|e| e.to_string())?;
+    sink.set_volume(0.0);
+    sink.append(source);
+    let crossfade = Duration::from_secs_f32(CROSSFADE);
+    Ok(Track {
+        sink,
+        fade: 0.0,
+        ends: length.map(|length| Instant::now() + length.saturating_sub(crossfade)),
+    })
+}
 
-    // The sound plays in a separate audio thread,
-    // so we need to keep the main thread alive while it's playing.
-    std::thread::sleep(std::time::Duration::from_millis(400));
+// Helper to list the music files in a folder, sorted by name
+fn tracks_in(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec!();
+    };
+    let mut tracks: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        })
+        .collect();
+    tracks.sort();
+    tracks
 }
@@ -0,0 +1,951 @@
+extern crate gl;
+use crate::assets::TextureHandle;
+use crate::bounds::{BoundingSphere, Frustum};
+use crate::map::PointLight;
+use crate::meshloader::Mesh;
+use crate::shader::ShaderProgram;
+use glam::{Mat4, Vec3, Vec3A};
+use std::collections::HashMap;
+use std::rc::Weak;
+
+// Handle to a mesh uploaded by the renderer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MeshHandle(pub usize);
+
+// Handle to a shader program owned by the backend (index into GlBackend::programs)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProgramHandle(pub usize);
+
+// Surface properties of a draw item, see the Material struct in lighting.fs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    pub color: Vec3A,           // albedo, multiplied by the texture when textured
+    pub texture: TextureHandle,
+    pub textured: bool,
+    pub ambient: f32,           // ambient strength
+    pub specular: f32,          // specular strength, 0.0 for matte surfaces
+    pub shininess: f32,         // specular exponent, higher is a tighter highlight
+    pub emissive: Vec3A,        // light given off regardless of lighting
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            color: Vec3A::ONE,
+            texture: 0,
+            textured: true,
+            ambient: 0.1,
+            specular: 0.0,
+            shininess: 32.0,
+            emissive: Vec3A::ZERO,
+        }
+    }
+}
+
+// Material methods
+impl Material {
+    // Material constructor for a matte textured surface of some colour
+    pub fn new(color: Vec3A) -> Self {
+        Material {
+            color,
+            ..Default::default()
+        }
+    }
+
+    // Method to check if two materials differ only in colour, so they can share an instanced draw
+    pub fn same_surface(&self, other: &Material) -> bool {
+        Material {
+            color: self.color,
+            ..*other
+        } == *self
+    }
+}
+
+// One object to draw this frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawItem {
+    pub mesh: MeshHandle,
+    pub program: ProgramHandle,
+    pub material: Material,
+    pub transform: Mat4,
+    pub bounds: Option<BoundingSphere>, // world space, items outside the view are skipped; None is never culled
+}
+
+// Per instance data for instanced draws, matching the INSTANCED attributes of lighting.vs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instance {
+    pub transform: Mat4,
+    pub color: Vec3A,
+}
+
+// Uniforms shared by every draw item in a frame, set once per program
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameUniforms {
+    pub proj: Mat4,
+    pub view: Mat4,
+    pub view_pos: Vec3A, // camera eye, for specular highlights
+    pub point_lights: Vec<PointLight>, // at most the MAX_LIGHTS the shaders were built with
+    pub sun_direction: Vec3A, // normalized direction the sunlight travels
+    pub sun_color: Vec3A,
+    pub light_space: Option<Mat4>, // sun view-projection for the shadow pass, None disables shadows
+    pub fog_color: Vec3A,
+    pub fog_density: f32,
+    pub skybox: Option<Skybox>, // drawn behind everything after the main pass
+}
+
+// Cubemap sky drawn with a skybox program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Skybox {
+    pub program: ProgramHandle,
+    pub cubemap: TextureHandle,
+    pub tint: Vec3A,
+}
+
+// Texture unit the shadow map is bound to during the main pass
+pub const SHADOW_MAP_UNIT: u32 = 1;
+
+// Method to build the view-projection matrix of a directional light covering a sphere of
+// `radius` around `center`, used to render and sample the shadow map
+pub fn directional_light_space(direction: Vec3A, center: Vec3A, radius: f32) -> Mat4 {
+    let eye = center - direction * 2.0 * radius;
+    let up = if direction.cross(Vec3A::Y).length_squared() < 1e-6 {
+        Vec3::X
+    } else {
+        Vec3::Y
+    };
+    let view = Mat4::look_at_rh(eye.into(), center.into(), up);
+    let proj = Mat4::orthographic_rh_gl(-radius, radius, -radius, radius, radius, 3.0 * radius);
+    proj * view
+}
+
+// Low level drawing operations, implemented with OpenGL by GlBackend and recorded by
+// RecordingBackend so the renderer's draw logic can be checked without a GPU
+pub trait Backend {
+    type Mesh;
+
+    // Uploads interleaved position/normal/texcoord vertex data, reusing `mesh` if given
+    fn upload_mesh(&mut self, mesh: Option<&Self::Mesh>, vertices: &[f32]) -> Self::Mesh;
+    fn delete_mesh(&mut self, mesh: Self::Mesh);
+    fn use_program(&mut self, program: ProgramHandle);
+    fn set_mat4(&mut self, name: &str, value: &Mat4);
+    fn set_vec3(&mut self, name: &str, value: Vec3A);
+    fn set_vec3_array(&mut self, name: &str, values: &[Vec3]);
+    fn set_float(&mut self, name: &str, value: f32);
+    fn set_int(&mut self, name: &str, value: i32);
+    fn bind_texture(&mut self, texture: TextureHandle);
+    fn draw(&mut self, mesh: &Self::Mesh);
+    fn draw_instanced(&mut self, mesh: &Self::Mesh, instances: &[Instance]);
+    // Redirects draws into the depth only shadow map until end_shadow_pass, which binds the
+    // shadow map to SHADOW_MAP_UNIT for the main pass
+    // Returns false if there is no shadow map to draw into, and the frame is drawn without shadows
+    fn begin_shadow_pass(&mut self) -> bool;
+    fn end_shadow_pass(&mut self);
+    // Draws a unit cube textured with `cubemap` at the far plane, behind everything drawn so far
+    fn draw_skybox(&mut self, cubemap: TextureHandle);
+}
+
+// Mesh uploaded by the renderer, tracking the source mesh so edits and reloads are re-uploaded
+struct UploadedMesh<M> {
+    gpu: M,
+    source: Weak<()>, // the source mesh's identity, dead once the mesh is dropped
+    revision: u64,
+}
+
+// Renderer, owns the GPU copies of meshes and draws the items submitted each frame
+pub struct Renderer<B: Backend> {
+    pub backend: B,
+    meshes: HashMap<MeshHandle, UploadedMesh<B::Mesh>>,
+    by_source: HashMap<*const (), MeshHandle>,
+    next_handle: usize,
+    queue: Vec<DrawItem>,
+    instanced: Vec<ProgramHandle>, // programs that read transform and colour per instance
+    shadow_programs: Vec<(ProgramHandle, ProgramHandle)>, // program -> its depth only variant
+}
+
+impl<B: Backend> Renderer<B> {
+    // Renderer constructor
+    pub fn new(backend: B) -> Self {
+        Renderer {
+            backend,
+            meshes: HashMap::new(),
+            by_source: HashMap::new(),
+            next_handle: 0,
+            queue: vec!(),
+            instanced: vec!(),
+            shadow_programs: vec!(),
+        }
+    }
+
+    // Method to set the depth only program used to draw items of `program` into the shadow map
+    // Items whose program has no shadow program do not cast shadows
+    pub fn set_shadow_program(&mut self, program: ProgramHandle, shadow_program: ProgramHandle) {
+        self.shadow_programs.retain(|(p, _)| *p != program);
+        self.shadow_programs.push((program, shadow_program));
+    }
+
+    // Method to mark a program as instanced (built with the INSTANCED define)
+    // Items using it that share a mesh and texture are drawn with a single instanced call
+    pub fn set_instanced(&mut self, program: ProgramHandle) {
+        if !self.instanced.contains(&program) {
+            self.instanced.push(program);
+        }
+    }
+
+    // Method to get the handle for a mesh, uploading it the first time it is seen and again
+    // whenever it has been modified. Entities sharing a mesh share one upload.
+    pub fn mesh(&mut self, mesh: &Mesh) -> MeshHandle {
+        let key = mesh.id().key();
+        if let Some(&handle) = self.by_source.get(&key) {
+            let uploaded = self.meshes.get_mut(&handle).unwrap();
+            if uploaded.source.ptr_eq(&mesh.id().downgrade()) {
+                if uploaded.revision != mesh.revision {
+                    uploaded.gpu = self.backend.upload_mesh(Some(&uploaded.gpu), &mesh.vertices_flattened());
+                    uploaded.revision = mesh.revision;
+                }
+                return handle;
+            }
+            // The old mesh was dropped and its identity's address reused
+            self.by_source.remove(&key);
+            if let Some(old) = self.meshes.remove(&handle) {
+                self.backend.delete_mesh(old.gpu);
+            }
+        }
+        let handle = MeshHandle(self.next_handle);
+        self.next_handle += 1;
+        let gpu = self.backend.upload_mesh(None, &mesh.vertices_flattened());
+        self.meshes.insert(
+            handle,
+            UploadedMesh {
+                gpu,
+                source: mesh.id().downgrade(),
+                revision: mesh.revision,
+            },
+        );
+        self.by_source.insert(key, handle);
+        handle
+    }
+
+    // Method to queue an item to be drawn by the next call to render
+    pub fn submit(&mut self, item: DrawItem) {
+        self.queue.push(item);
+    }
+
+    // Method to draw every queued item and clear the queue
+    // If the frame has a light space matrix, items whose program has a shadow program are first
+    // drawn into the shadow map. Items outside the view frustum are then dropped, and the rest
+    // are sorted by program then texture to minimise state
+    // changes, and the frame uniforms are set once for each program used.
+    pub fn render(&mut self, frame: &FrameUniforms) {
+        let mut queue = std::mem::take(&mut self.queue);
+
+        let mut shadows = false;
+        if let Some(light_space) = frame.light_space {
+            let mut casters: Vec<DrawItem> = queue
+                .iter()
+                .filter_map(|item| {
+                    let &(_, program) = self.shadow_programs.iter().find(|(p, _)| *p == item.program)?;
+                    Some(DrawItem { program, ..*item })
+                })
+                .collect();
+            casters.sort_by_key(|item| (item.program, item.material.texture, item.mesh));
+            if self.backend.begin_shadow_pass() {
+                self.draw_items(&casters, &|backend: &mut B| {
+                    backend.set_mat4("lightSpace", &light_space);
+                });
+                self.backend.end_shadow_pass();
+                shadows = true;
+            }
+        }
+
+        // Shadow casters outside the view can still shadow what is visible, so only the main pass
+        // is culled
+        let frustum = Frustum::from_matrix(frame.proj * frame.view);
+        queue.retain(|item| match item.bounds {
+            Some(bounds) => frustum.intersects_sphere(&bounds),
+            None => true,
+        });
+        queue.sort_by_key(|item| (item.program, item.material.texture, item.mesh));
+        self.draw_items(&queue, &|backend: &mut B| {
+            backend.set_mat4("proj", &frame.proj);
+            backend.set_mat4("view", &frame.view);
+            backend.set_vec3("viewPos", frame.view_pos);
+            let lights = &frame.point_lights;
+            backend.set_int("lightCount", lights.len() as i32);
+            backend.set_vec3_array("lightPos", &lights.iter().map(|l| l.position).collect::<Vec<Vec3>>());
+            backend.set_vec3_array("lightColor", &lights.iter().map(|l| l.color).collect::<Vec<Vec3>>());
+            backend.set_vec3_array(
+                "lightAttenuation",
+                &lights.iter().map(|l| l.attenuation).collect::<Vec<Vec3>>(),
+            );
+            backend.set_vec3("sunDirection", frame.sun_direction);
+            backend.set_vec3("sunColor", frame.sun_color);
+            backend.set_mat4("lightSpace", &frame.light_space.unwrap_or(Mat4::IDENTITY));
+            backend.set_int("shadowsEnabled", shadows as i32);
+            backend.set_int("shadowMap", SHADOW_MAP_UNIT as i32);
+            backend.set_vec3("fogColor", frame.fog_color);
+            backend.set_float("fogDensity", frame.fog_density);
+        });
+
+        if let Some(skybox) = frame.skybox {
+            self.backend.use_program(skybox.program);
+            self.backend.set_mat4("proj", &frame.proj);
+            self.backend.set_mat4("view", &frame.view);
+            self.backend.set_vec3("skyTint", skybox.tint);
+            self.backend.set_int("skybox", 0);
+            self.backend.draw_skybox(skybox.cubemap);
+        }
+
+        // Free meshes nothing refers to any more
+        let dead: Vec<MeshHandle> = self
+            .meshes
+            .iter()
+            .filter(|(_, m)| m.source.strong_count() == 0)
+            .map(|(&h, _)| h)
+            .collect();
+        for handle in dead {
+            if let Some(old) = self.meshes.remove(&handle) {
+                self.by_source.retain(|_, h| *h != handle);
+                self.backend.delete_mesh(old.gpu);
+            }
+        }
+
+        queue.clear();
+        self.queue = queue;
+    }
+
+    // Helper for render, draws sorted items calling set_frame_uniforms whenever the program changes
+    fn draw_items(&mut self, queue: &[DrawItem], set_frame_uniforms: &dyn Fn(&mut B)) {
+        let mut program = None;
+        let mut texture = None;
+        let mut i = 0;
+        while i < queue.len() {
+            let item = queue[i];
+            let Some(uploaded) = self.meshes.get(&item.mesh) else {
+                i += 1;
+                continue;
+            };
+            if program != Some(item.program) {
+                self.backend.use_program(item.program);
+                set_frame_uniforms(&mut self.backend);
+                program = Some(item.program);
+                texture = None;
+            }
+            if texture != Some(item.material.texture) {
+                self.backend.bind_texture(item.material.texture);
+                texture = Some(item.material.texture);
+            }
+            if self.instanced.contains(&item.program) {
+                // The queue is sorted, so items sharing this mesh and texture follow on
+                let batch = queue[i..]
+                    .iter()
+                    .take_while(|other| {
+                        other.program == item.program
+                            && other.mesh == item.mesh
+                            && other.material.same_surface(&item.material)
+                    })
+                    .map(|other| Instance {
+                        transform: other.transform,
+                        color: other.material.color,
+                    })
+                    .collect::<Vec<Instance>>();
+                set_material(&mut self.backend, &item.material);
+                self.backend.draw_instanced(&uploaded.gpu, &batch);
+                i += batch.len();
+            } else {
+                self.backend.set_mat4("model", &item.transform);
+                self.backend.set_vec3("objectColor", item.material.color);
+                set_material(&mut self.backend, &item.material);
+                self.backend.draw(&uploaded.gpu);
+                i += 1;
+            }
+        }
+    }
+}
+
+// Helper to send the material uniforms other than the (possibly per instance) colour
+fn set_material<B: Backend>(backend: &mut B, material: &Material) {
+    backend.set_float("material.ambient", material.ambient);
+    backend.set_float("material.specular", material.specular);
+    backend.set_float("material.shininess", material.shininess);
+    backend.set_vec3("material.emissive", material.emissive);
+    backend.set_int("material.textured", material.textured as i32);
+}
+
+// VAO/VBO pair for a mesh uploaded with OpenGL
+#[derive(Debug)]
+pub struct GlMesh {
+    pub vao: u32,
+    pub vbo: u32,
+    pub vertex_count: i32,
+}
+
+// OpenGL backend, owns the shader programs addressed by ProgramHandle
+pub struct GlBackend {
+    pub programs: Vec<ShaderProgram>,
+    current: usize,
+    instance_vbo: u32, // shared per instance buffer, created on the first instanced draw
+    pub shadow_map_size: i32,      // set to 0 if the shadow map can't be created, disabling shadows
+    shadow_map: Option<ShadowMap>, // created on the first shadow pass
+    viewport: [i32; 4],           // main viewport, restored after the shadow pass
+    skybox_vao: u32,               // unit cube, created on the first skybox draw
+}
+
+// Depth texture and framebuffer the shadow pass renders into
+struct ShadowMap {
+    fbo: u32,
+    depth_texture: u32,
+    size: i32,
+}
+
+impl ShadowMap {
+    // Shadow map constructor, depth outside the map reads as fully lit
+    // Fails if the driver can't render to a depth texture of this size
+    unsafe fn new(size: i32) -> Result<Self, String> {
+        let mut depth_texture = 0;
+        gl::GenTextures(1, &mut depth_texture);
+        gl::BindTexture(gl::TEXTURE_2D, depth_texture);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::DEPTH_COMPONENT24 as i32,
+            size,
+            size,
+            0,
+            gl::DEPTH_COMPONENT,
+            gl::FLOAT,
+            std::ptr::null(),
+        );
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_BORDER as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_BORDER as i32);
+        let border = [1.0f32; 4];
+        gl::TexParameterfv(gl::TEXTURE_2D, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+
+        let mut fbo = 0;
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_ATTACHMENT,
+            gl::TEXTURE_2D,
+            depth_texture,
+            0,
+        );
+        gl::DrawBuffer(gl::NONE);
+        gl::ReadBuffer(gl::NONE);
+        let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            gl::DeleteFramebuffers(1, &fbo);
+            gl::DeleteTextures(1, &depth_texture);
+            return Err(format!("{}x{} shadow map framebuffer incomplete (0x{:x})", size, size, status));
+        }
+        Ok(ShadowMap {
+            fbo,
+            depth_texture,
+            size,
+        })
+    }
+}
+
+impl GlBackend {
+    // GL backend constructor, requires a current GL context
+    pub fn new(programs: Vec<ShaderProgram>) -> Self {
+        GlBackend {
+            programs,
+            current: 0,
+            instance_vbo: 0,
+            shadow_map_size: 2048,
+            shadow_map: None,
+            viewport: [0; 4],
+            skybox_vao: 0,
+        }
+    }
+
+    // Method to get the program currently in use
+    pub fn program(&self) -> &ShaderProgram {
+        &self.programs[self.current]
+    }
+}
+
+impl Backend for GlBackend {
+    type Mesh = GlMesh;
+
+    fn upload_mesh(&mut self, mesh: Option<&GlMesh>, vertices: &[f32]) -> GlMesh {
+        let stride = (9 * std::mem::size_of::<f32>()) as i32;
+        let (mut vao, mut vbo) = mesh.map_or((0, 0), |m| (m.vao, m.vbo));
+        unsafe {
+            if mesh.is_none() {
+                gl::GenVertexArrays(1, &mut vao);
+                assert_ne!(vao, 0);
+                gl::GenBuffers(1, &mut vbo);
+                assert_ne!(vbo, 0);
+            }
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(vertices) as isize,
+                vertices.as_ptr().cast(),
+                gl::STATIC_DRAW,
+            );
+            for (attrib, offset) in [(0, 0), (1, 3), (2, 6)] {
+                gl::VertexAttribPointer(
+                    attrib,
+                    3,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    stride,
+                    (offset * std::mem::size_of::<f32>()) as *const _,
+                );
+                gl::EnableVertexAttribArray(attrib);
+            }
+        }
+        GlMesh {
+            vao,
+            vbo,
+            vertex_count: (vertices.len() / 9) as i32,
+        }
+    }
+
+    fn delete_mesh(&mut self, mesh: GlMesh) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &mesh.vao);
+            gl::DeleteBuffers(1, &mesh.vbo);
+        }
+    }
+
+    fn use_program(&mut self, program: ProgramHandle) {
+        self.current = program.0;
+        self.program().use_program();
+        self.program().set_sampler("ourTexture", 0);
+    }
+
+    fn set_mat4(&mut self, name: &str, value: &Mat4) {
+        self.program().set_mat4(name, value);
+    }
+
+    fn set_vec3(&mut self, name: &str, value: Vec3A) {
+        self.program().set_vec3(name, value);
+    }
+
+    fn set_vec3_array(&mut self, name: &str, values: &[Vec3]) {
+        self.program().set_vec3_array(name, values);
+    }
+
+    fn set_float(&mut self, name: &str, value: f32) {
+        self.program().set_float(name, value);
+    }
+
+    fn set_int(&mut self, name: &str, value: i32) {
+        self.program().set_int(name, value);
+    }
+
+    fn bind_texture(&mut self, texture: TextureHandle) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+        }
+    }
+
+    fn draw(&mut self, mesh: &GlMesh) {
+        unsafe {
+            gl::BindVertexArray(mesh.vao);
+            gl::DrawArrays(gl::TRIANGLES, 0, mesh.vertex_count);
+        }
+    }
+
+    fn draw_instanced(&mut self, mesh: &GlMesh, instances: &[Instance]) {
+        // mat4 transform (locations 3 to 6, one per column) then vec3 colour (location 7)
+        let mut data: Vec<f32> = Vec::with_capacity(instances.len() * 19);
+        for instance in instances {
+            data.extend_from_slice(&instance.transform.to_cols_array());
+            data.extend_from_slice(&instance.color.to_array());
+        }
+        let stride = (19 * std::mem::size_of::<f32>()) as i32;
+        unsafe {
+            if self.instance_vbo == 0 {
+                gl::GenBuffers(1, &mut self.instance_vbo);
+                assert_ne!(self.instance_vbo, 0);
+            }
+            gl::BindVertexArray(mesh.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(data.as_slice()) as isize,
+                data.as_ptr().cast(),
+                gl::STREAM_DRAW,
+            );
+            for (attrib, size, offset) in [(3, 4, 0), (4, 4, 4), (5, 4, 8), (6, 4, 12), (7, 3, 16)] {
+                gl::VertexAttribPointer(
+                    attrib,
+                    size,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    stride,
+                    (offset * std::mem::size_of::<f32>()) as *const _,
+                );
+                gl::EnableVertexAttribArray(attrib);
+                gl::VertexAttribDivisor(attrib, 1);
+            }
+            gl::DrawArraysInstanced(gl::TRIANGLES, 0, mesh.vertex_count, instances.len() as i32);
+        }
+    }
+
+    fn begin_shadow_pass(&mut self) -> bool {
+        unsafe {
+            let stale = match &self.shadow_map {
+                Some(shadow_map) => shadow_map.size != self.shadow_map_size,
+                None => true,
+            };
+            if stale {
+                if let Some(old) = self.shadow_map.take() {
+                    gl::DeleteFramebuffers(1, &old.fbo);
+                    gl::DeleteTextures(1, &old.depth_texture);
+                }
+                if self.shadow_map_size <= 0 {
+                    return false;
+                }
+                match ShadowMap::new(self.shadow_map_size) {
+                    Ok(shadow_map) => self.shadow_map = Some(shadow_map),
+                    Err(e) => {
+                        println!("Playing without shadows: {}", e);
+                        self.shadow_map_size = 0;
+                        return false;
+                    }
+                }
+            }
+            let Some(shadow_map) = &self.shadow_map else {
+                return false;
+            };
+            gl::GetIntegerv(gl::VIEWPORT, self.viewport.as_mut_ptr());
+            gl::BindFramebuffer(gl::FRAMEBUFFER, shadow_map.fbo);
+            gl::Viewport(0, 0, shadow_map.size, shadow_map.size);
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+        }
+        true
+    }
+
+    fn draw_skybox(&mut self, cubemap: TextureHandle) {
+        unsafe {
+            if self.skybox_vao == 0 {
+                self.skybox_vao = skybox_cube();
+            }
+            gl::DepthFunc(gl::LEQUAL);
+            gl::DepthMask(gl::FALSE);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+            gl::BindVertexArray(self.skybox_vao);
+            gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            gl::DepthMask(gl::TRUE);
+            gl::DepthFunc(gl::LESS);
+        }
+    }
+
+    fn end_shadow_pass(&mut self) {
+        let [x, y, w, h] = self.viewport;
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(x, y, w, h);
+            if let Some(shadow_map) = &self.shadow_map {
+                gl::ActiveTexture(gl::TEXTURE0 + SHADOW_MAP_UNIT);
+                gl::BindTexture(gl::TEXTURE_2D, shadow_map.depth_texture);
+                gl::ActiveTexture(gl::TEXTURE0);
+            }
+        }
+    }
+}
+
+// Helper to create the VAO of a unit cube (positions only) for skybox drawing
+unsafe fn skybox_cube() -> u32 {
+    let corners = [
+        [-1.0, -1.0, -1.0f32], [1.0, -1.0, -1.0], [1.0, 1.0, -1.0], [-1.0, 1.0, -1.0],
+        [-1.0, -1.0, 1.0], [1.0, -1.0, 1.0], [1.0, 1.0, 1.0], [-1.0, 1.0, 1.0],
+    ];
+    // two triangles per face, winding is irrelevant as face culling is off
+    let faces = [
+        [0, 1, 2, 2, 3, 0], [4, 5, 6, 6, 7, 4], [0, 4, 7, 7, 3, 0],
+        [1, 5, 6, 6, 2, 1], [3, 2, 6, 6, 7, 3], [0, 1, 5, 5, 4, 0],
+    ];
+    let vertices: Vec<f32> = faces.iter().flatten().flat_map(|&i| corners[i]).collect();
+    let (mut vao, mut vbo) = (0, 0);
+    gl::GenVertexArrays(1, &mut vao);
+    gl::GenBuffers(1, &mut vbo);
+    gl::BindVertexArray(vao);
+    gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+    gl::BufferData(
+        gl::ARRAY_BUFFER,
+        std::mem::size_of_val(vertices.as_slice()) as isize,
+        vertices.as_ptr().cast(),
+        gl::STATIC_DRAW,
+    );
+    gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, (3 * std::mem::size_of::<f32>()) as i32, std::ptr::null());
+    gl::EnableVertexAttribArray(0);
+    vao
+}
+
+// A call made on a RecordingBackend
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    UploadMesh { mesh: usize, vertex_count: usize },
+    DeleteMesh(usize),
+    UseProgram(ProgramHandle),
+    SetMat4(String, Mat4),
+    SetVec3(String, Vec3A),
+    SetVec3Array(String, Vec<Vec3>),
+    SetFloat(String, f32),
+    SetInt(String, i32),
+    BindTexture(TextureHandle),
+    Draw(usize),
+    DrawInstanced(usize, Vec<Instance>),
+    BeginShadowPass,
+    EndShadowPass,
+    DrawSkybox(TextureHandle),
+}
+
+// Backend that records every call instead of drawing, for checking draw logic without a GPU
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct RecordingBackend {
+    pub calls: Vec<Call>,
+    next_mesh: usize,
+}
+
+#[cfg(test)]
+impl Backend for RecordingBackend {
+    type Mesh = usize;
+
+    fn upload_mesh(&mut self, mesh: Option<&usize>, vertices: &[f32]) -> usize {
+        let id = match mesh {
+            Some(&id) => id,
+            None => {
+                self.next_mesh += 1;
+                self.next_mesh
+            }
+        };
+        self.calls.push(Call::UploadMesh {
+            mesh: id,
+            vertex_count: vertices.len() / 9,
+        });
+        id
+    }
+
+    fn delete_mesh(&mut self, mesh: usize) {
+        self.calls.push(Call::DeleteMesh(mesh));
+    }
+
+    fn use_program(&mut self, program: ProgramHandle) {
+        self.calls.push(Call::UseProgram(program));
+    }
+
+    fn set_mat4(&mut self, name: &str, value: &Mat4) {
+        self.calls.push(Call::SetMat4(String::from(name), *value));
+    }
+
+    fn set_vec3(&mut self, name: &str, value: Vec3A) {
+        self.calls.push(Call::SetVec3(String::from(name), value));
+    }
+
+    fn set_vec3_array(&mut self, name: &str, values: &[Vec3]) {
+        self.calls.push(Call::SetVec3Array(String::from(name), values.to_vec()));
+    }
+
+    fn set_float(&mut self, name: &str, value: f32) {
+        self.calls.push(Call::SetFloat(String::from(name), value));
+    }
+
+    fn set_int(&mut self, name: &str, value: i32) {
+        self.calls.push(Call::SetInt(String::from(name), value));
+    }
+
+    fn bind_texture(&mut self, texture: TextureHandle) {
+        self.calls.push(Call::BindTexture(texture));
+    }
+
+    fn draw(&mut self, mesh: &usize) {
+        self.calls.push(Call::Draw(*mesh));
+    }
+
+    fn draw_instanced(&mut self, mesh: &usize, instances: &[Instance]) {
+        self.calls.push(Call::DrawInstanced(*mesh, instances.to_vec()));
+    }
+
+    fn begin_shadow_pass(&mut self) -> bool {
+        self.calls.push(Call::BeginShadowPass);
+        true
+    }
+
+    fn end_shadow_pass(&mut self) {
+        self.calls.push(Call::EndShadowPass);
+    }
+
+    fn draw_skybox(&mut self, cubemap: TextureHandle) {
+        self.calls.push(Call::DrawSkybox(cubemap));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::vec3a;
+    use std::rc::Rc;
+
+    // Helper to load a one triangle mesh, written to a temporary STL file
+    fn triangle(name: &str) -> Rc<Mesh> {
+        let path = std::env::temp_dir().join(format!("sephere-renderer-{}-{}.stl", std::process::id(), name));
+        let stl = "solid t\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\n\
+                   endloop\nendfacet\nendsolid t\n";
+        std::fs::write(&path, stl).unwrap();
+        let mesh = Mesh::new(&path.to_string_lossy(), Vec3A::ONE).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        Rc::new(mesh)
+    }
+
+    // Helper to build a frame without lights, shadows or sky
+    fn frame() -> FrameUniforms {
+        FrameUniforms {
+            proj: Mat4::IDENTITY,
+            view: Mat4::IDENTITY,
+            view_pos: Vec3A::ZERO,
+            point_lights: vec!(),
+            sun_direction: Vec3A::NEG_Y,
+            sun_color: Vec3A::ONE,
+            light_space: None,
+            fog_color: Vec3A::ZERO,
+            fog_density: 0.0,
+            skybox: None,
+        }
+    }
+
+    // Helper to build an item that is never culled
+    fn item(mesh: MeshHandle, program: usize, texture: TextureHandle, color: Vec3A) -> DrawItem {
+        DrawItem {
+            mesh,
+            program: ProgramHandle(program),
+            material: Material {
+                texture,
+                ..Material::new(color)
+            },
+            transform: Mat4::IDENTITY,
+            bounds: None,
+        }
+    }
+
+    #[test]
+    fn items_are_sorted_by_program_then_texture() {
+        let mesh = triangle("sorted");
+        let mut renderer = Renderer::new(RecordingBackend::default());
+        let handle = renderer.mesh(&mesh);
+        renderer.submit(item(handle, 2, 7, Vec3A::ONE));
+        renderer.submit(item(handle, 1, 9, Vec3A::ONE));
+        renderer.submit(item(handle, 1, 3, Vec3A::ONE));
+        renderer.render(&frame());
+
+        let order: Vec<&Call> = renderer
+            .backend
+            .calls
+            .iter()
+            .filter(|c| matches!(c, Call::UseProgram(_) | Call::BindTexture(_)))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                &Call::UseProgram(ProgramHandle(1)),
+                &Call::BindTexture(3),
+                &Call::BindTexture(9),
+                &Call::UseProgram(ProgramHandle(2)),
+                &Call::BindTexture(7),
+            ]
+        );
+    }
+
+    #[test]
+    fn frame_uniforms_are_set_once_per_program() {
+        let mesh = triangle("uniforms");
+        let mut renderer = Renderer::new(RecordingBackend::default());
+        let handle = renderer.mesh(&mesh);
+        for program in [0, 1, 0, 1, 1] {
+            renderer.submit(item(handle, program, 0, Vec3A::ONE));
+        }
+        renderer.render(&frame());
+
+        let calls = &renderer.backend.calls;
+        let proj = calls.iter().filter(|c| matches!(c, Call::SetMat4(name, _) if name == "proj")).count();
+        let draws = calls.iter().filter(|c| matches!(c, Call::Draw(_))).count();
+        assert_eq!(proj, 2);
+        assert_eq!(draws, 5);
+    }
+
+    #[test]
+    fn matching_items_share_an_instanced_draw() {
+        let mesh = triangle("instanced");
+        let mut renderer = Renderer::new(RecordingBackend::default());
+        renderer.set_instanced(ProgramHandle(1));
+        let handle = renderer.mesh(&mesh);
+        let colors = [vec3a(1.0, 0.0, 0.0), vec3a(0.0, 1.0, 0.0), vec3a(0.0, 0.0, 1.0)];
+        for color in colors {
+            renderer.submit(item(handle, 1, 0, color));
+        }
+        renderer.render(&frame());
+
+        let calls = &renderer.backend.calls;
+        let instanced: Vec<&Vec<Instance>> = calls
+            .iter()
+            .filter_map(|c| match c {
+                Call::DrawInstanced(_, instances) => Some(instances),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(instanced.len(), 1);
+        assert_eq!(instanced[0].iter().map(|i| i.color).collect::<Vec<Vec3A>>(), colors);
+        assert!(!calls.iter().any(|c| matches!(c, Call::Draw(_))));
+    }
+
+    #[test]
+    fn edited_meshes_are_uploaded_again() {
+        let mut mesh = triangle("edited");
+        let mut renderer = Renderer::new(RecordingBackend::default());
+        let handle = renderer.mesh(&mesh);
+        assert_eq!(renderer.mesh(&mesh), handle);
+        let uploads = |r: &Renderer<RecordingBackend>| {
+            r.backend.calls.iter().filter(|c| matches!(c, Call::UploadMesh { .. })).count()
+        };
+        assert_eq!(uploads(&renderer), 1);
+
+        // edited the way entities and terrain chunks edit their meshes
+        let revision = mesh.revision;
+        Rc::make_mut(&mut mesh).mutate(0, Vec3A::Y, 0.5);
+        assert!(mesh.revision > revision);
+        assert_eq!(renderer.mesh(&mesh), handle);
+        assert_eq!(uploads(&renderer), 2);
+        assert_eq!(
+            renderer.backend.calls.last(),
+            Some(&Call::UploadMesh { mesh: 1, vertex_count: 3 })
+        );
+
+        // the buffer was refilled in place, so there is nothing to free
+        renderer.render(&frame());
+        assert!(!renderer.backend.calls.iter().any(|c| matches!(c, Call::DeleteMesh(_))));
+    }
+
+    #[test]
+    fn dropped_meshes_are_freed() {
+        let kept = triangle("kept");
+        let dropped = triangle("dropped");
+        let mut renderer = Renderer::new(RecordingBackend::default());
+        renderer.mesh(&kept);
+        renderer.mesh(&dropped);
+        drop(dropped);
+        renderer.render(&frame());
+
+        let deleted: Vec<&Call> = renderer
+            .backend
+            .calls
+            .iter()
+            .filter(|c| matches!(c, Call::DeleteMesh(_)))
+            .collect();
+        assert_eq!(deleted, [&Call::DeleteMesh(2)]);
+    }
+}
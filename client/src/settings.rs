@@ -0,0 +1,177 @@
+use crate::camera::CameraMode;
+use crate::input::MouseLook;
+use std::fmt;
+use std::path::PathBuf;
+
+// Environment variable that overrides the settings file location
+pub const SETTINGS_ENV_VAR: &str = "SEPHERE_SETTINGS";
+const SETTINGS_FILE: &str = "settings.cfg";
+const SHADOW_MAP_SIZES: std::ops::RangeInclusive<u32> = 256..=8192; // powers of two in this range
+
+// User settings, read from a `key = value` file in the user config directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub shadows: bool,
+    pub shadow_map_size: u32,
+    pub day_night: bool, // animate the sun if the map defines a day/night cycle
+    pub camera_mode: CameraMode, // mode the camera starts in, C cycles through them in game
+    pub orbit_radius: f32,
+    pub chase_distance: f32,
+    pub chase_height: f32,
+    pub first_person_fov: f32, // degrees
+    pub spectator_speed: f32,  // units per second
+    pub mouse_look: MouseLook,
+    pub mouse_sensitivity: f32, // radians per pixel of mouse movement
+    pub invert_y: bool,
+    pub master_volume: f32, // from 0 to 1
+    pub sfx_volume: f32,
+    pub music_volume: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            shadows: true,
+            shadow_map_size: 2048,
+            day_night: false,
+            camera_mode: CameraMode::Orbit,
+            orbit_radius: 2.0,
+            chase_distance: 1.5,
+            chase_height: 0.6,
+            first_person_fov: 72.0,
+            spectator_speed: 4.0,
+            mouse_look: MouseLook::Toggle,
+            mouse_sensitivity: 0.003,
+            invert_y: false,
+            master_volume: 1.0,
+            sfx_volume: 1.0,
+            music_volume: 0.6,
+        }
+    }
+}
+
+impl fmt::Display for Settings {
+    // Writes the settings in the same format they are read in
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# se-phere client settings")?;
+        writeln!(f, "shadows = {}", self.shadows)?;
+        writeln!(f, "shadow_map_size = {}", self.shadow_map_size)?;
+        writeln!(f, "day_night = {}", self.day_night)?;
+        writeln!(f, "camera_mode = {}", self.camera_mode)?;
+        writeln!(f, "orbit_radius = {}", self.orbit_radius)?;
+        writeln!(f, "chase_distance = {}", self.chase_distance)?;
+        writeln!(f, "chase_height = {}", self.chase_height)?;
+        writeln!(f, "first_person_fov = {}", self.first_person_fov)?;
+        writeln!(f, "spectator_speed = {}", self.spectator_speed)?;
+        writeln!(f, "mouse_look = {}", self.mouse_look)?;
+        writeln!(f, "mouse_sensitivity = {}", self.mouse_sensitivity)?;
+        writeln!(f, "invert_y = {}", self.invert_y)?;
+        writeln!(f, "master_volume = {}", self.master_volume)?;
+        writeln!(f, "sfx_volume = {}", self.sfx_volume)?;
+        writeln!(f, "music_volume = {}", self.music_volume)
+    }
+}
+
+impl Settings {
+    // Method to load the settings file, falling back to defaults for anything missing or invalid
+    // A default file is written on first run so there is something to edit
+    pub fn load() -> Self {
+        let path = settings_path();
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            let settings = Settings::default();
+            if let Err(e) = settings.save() {
+                println!("Could not write default settings to {}: {}", path.display(), e);
+            }
+            return settings;
+        };
+        let mut settings = Settings::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                println!("{}:{}: expected `key = value`", path.display(), i + 1);
+                continue;
+            };
+            if let Err(reason) = settings.set(key.trim(), value.trim()) {
+                println!("{}:{}: {}", path.display(), i + 1, reason);
+            }
+        }
+        settings
+    }
+
+    // Method to write the settings file, creating the config directory if needed
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = settings_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_string())
+    }
+
+    // Setter for a single setting by its key in the settings file
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "shadows" => self.shadows = parse(key, value)?,
+            "shadow_map_size" => {
+                let size: u32 = parse(key, value)?;
+                if !SHADOW_MAP_SIZES.contains(&size) || !size.is_power_of_two() {
+                    return Err(format!(
+                        "`{}` must be a power of two from {} to {}",
+                        key,
+                        SHADOW_MAP_SIZES.start(),
+                        SHADOW_MAP_SIZES.end()
+                    ));
+                }
+                self.shadow_map_size = size;
+            }
+            "day_night" => self.day_night = parse(key, value)?,
+            "camera_mode" => self.camera_mode = parse(key, value)?,
+            "orbit_radius" => self.orbit_radius = parse_in(key, value, 0.1..=50.0)?,
+            "chase_distance" => self.chase_distance = parse_in(key, value, 0.1..=50.0)?,
+            "chase_height" => self.chase_height = parse_in(key, value, -10.0..=10.0)?,
+            "first_person_fov" => self.first_person_fov = parse_in(key, value, 10.0..=170.0)?,
+            "spectator_speed" => self.spectator_speed = parse_in(key, value, 0.1..=100.0)?,
+            "mouse_look" => self.mouse_look = parse(key, value)?,
+            "mouse_sensitivity" => self.mouse_sensitivity = parse_in(key, value, 0.0001..=0.1)?,
+            "invert_y" => self.invert_y = parse(key, value)?,
+            "master_volume" => self.master_volume = parse_in(key, value, 0.0..=1.0)?,
+            "sfx_volume" => self.sfx_volume = parse_in(key, value, 0.0..=1.0)?,
+            "music_volume" => self.music_volume = parse_in(key, value, 0.0..=1.0)?,
+            _ => return Err(format!("unknown setting `{}`", key)),
+        }
+        Ok(())
+    }
+}
+
+// Helper to parse a setting value, naming the setting on failure
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{}` for `{}`", value, key))
+}
+
+// Helper to parse a numeric setting that must fall within `range`, naming the range on failure
+fn parse_in<T>(key: &str, value: &str, range: std::ops::RangeInclusive<T>) -> Result<T, String>
+where
+    T: std::str::FromStr + PartialOrd + fmt::Display,
+{
+    let parsed = parse(key, value)?;
+    if !range.contains(&parsed) {
+        return Err(format!("`{}` must be from {} to {}, got {}", key, range.start(), range.end(), value));
+    }
+    Ok(parsed)
+}
+
+// Helper to get the settings file path: SEPHERE_SETTINGS, else the user config directory, else the
+// working directory
+pub fn settings_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(SETTINGS_ENV_VAR) {
+        return PathBuf::from(path);
+    }
+    match controls::config_dir() {
+        Some(dir) => dir.join(SETTINGS_FILE),
+        None => PathBuf::from(SETTINGS_FILE),
+    }
+}
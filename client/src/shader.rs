@@ -1,103 +1,295 @@
 extern crate gl;
 use gl::types::*;
 use gl::*;
+use glam::{Mat4, Vec3, Vec3A};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// Error returned when a shader program cannot be built, carrying the full info log
+#[derive(Debug)]
+pub enum ShaderError {
+    Io { path: PathBuf, source: std::io::Error },
+    Include { path: PathBuf, line: usize, reason: String },
+    Compile { stage: &'static str, path: PathBuf, log: String },
+    Link { vs_path: PathBuf, fs_path: PathBuf, log: String },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderError::Io { path, source } => {
+                write!(f, "Shader File: {} could not be read: {}", path.display(), source)
+            }
+            ShaderError::Include { path, line, reason } => {
+                write!(f, "Shader Include Error ({}:{}): {}", path.display(), line, reason)
+            }
+            ShaderError::Compile { stage, path, log } => {
+                write!(f, "{} Compile Error ({}):\n{}", stage, path.display(), log)
+            }
+            ShaderError::Link { vs_path, fs_path, log } => write!(
+                f,
+                "Program Link Error ({} + {}):\n{}",
+                vs_path.display(),
+                fs_path.display(),
+                log
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+// Allows shader loading to be propagated with `?` inside the game loop
+impl From<ShaderError> for std::io::Error {
+    fn from(e: ShaderError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    }
+}
 
 // Shader Program struct
 pub struct ShaderProgram {
-    pub vs: GLenum,
-    pub fs: GLenum,
     pub program: GLuint,
+    pub vs_path: PathBuf,
+    pub fs_path: PathBuf,
+    pub defines: Vec<(String, String)>, // injected after #version as `#define NAME VALUE`
+    pub sources: Vec<PathBuf>,          // every file read, including #include'd ones
+    locations: RefCell<HashMap<String, GLint>>,
 }
 
 // Shader Program methods
 impl ShaderProgram {
 
-    // Shader program constructor from shader files
-    pub unsafe fn new(vs_path: &str, fs_path: &str) -> Self {
-        let vs = CreateShader(VERTEX_SHADER);
-        let fs = CreateShader(FRAGMENT_SHADER);
-        let vs_source = std::fs::read_to_string(vs_path).expect(
-            format!(
-                "Shader File: {shader_path} not found!",
-                shader_path = vs_path
-            )
-            .as_str(),
-        );
-        ShaderSource(
-            vs,
-            1,
-            &(vs_source.as_bytes().as_ptr().cast()),
-            &(vs_source.len().try_into().unwrap()),
-        );
-        CompileShader(vs);
-        let mut success = 0;
-        GetShaderiv(vs, COMPILE_STATUS, &mut success);
-        if success == 0 {
-            let mut v: Vec<u8> = Vec::with_capacity(1024);
-            let mut log_len = 0_i32;
-            gl::GetShaderInfoLog(vs, 1024, &mut log_len, v.as_mut_ptr().cast());
-            v.set_len(log_len.try_into().unwrap());
-            panic!("Vertex Compile Error: {}", String::from_utf8_lossy(&v));
-        }
-        let fs_source = std::fs::read_to_string(fs_path).expect(
-            format!(
-                "Shader File: {shader_path} not found!",
-                shader_path = fs_path
-            )
-            .as_str(),
-        );
-        ShaderSource(
-            fs,
-            1,
-            &(fs_source.as_bytes().as_ptr().cast()),
-            &(fs_source.len().try_into().unwrap()),
-        );
-        CompileShader(fs);
-        let mut success = 0;
-        GetShaderiv(fs, COMPILE_STATUS, &mut success);
-        if success == 0 {
-            let mut v: Vec<u8> = Vec::with_capacity(1024);
-            let mut log_len = 0_i32;
-            GetShaderInfoLog(fs, 1024, &mut log_len, v.as_mut_ptr().cast());
-            v.set_len(log_len.try_into().unwrap());
-            panic!("Vertex Compile Error: {}", String::from_utf8_lossy(&v));
-        }
+    /// Shader program constructor from shader files
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn new(vs_path: &Path, fs_path: &Path) -> Result<Self, ShaderError> {
+        Self::with_defines(vs_path, fs_path, &[])
+    }
+
+    /// Shader program constructor from shader files, injecting `#define NAME VALUE` lines
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn with_defines(
+        vs_path: &Path,
+        fs_path: &Path,
+        defines: &[(&str, String)],
+    ) -> Result<Self, ShaderError> {
+        let defines: Vec<(String, String)> = defines
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        let mut sources = vec!();
+        let vs_source = preprocess(vs_path, &defines, &mut sources)?;
+        let fs_source = preprocess(fs_path, &defines, &mut sources)?;
+
+        let vs = compile(VERTEX_SHADER, vs_path, &vs_source)?;
+        let fs = match compile(FRAGMENT_SHADER, fs_path, &fs_source) {
+            Ok(fs) => fs,
+            Err(e) => {
+                DeleteShader(vs);
+                return Err(e);
+            }
+        };
         let program = CreateProgram();
         AttachShader(program, vs);
         AttachShader(program, fs);
         LinkProgram(program);
+        DeleteShader(vs);
+        DeleteShader(fs);
         let mut success = 0;
         GetProgramiv(program, gl::LINK_STATUS, &mut success);
         if success == 0 {
-            let mut v: Vec<u8> = Vec::with_capacity(1024);
-            let mut log_len = 0_i32;
-            GetProgramInfoLog(program, 1024, &mut log_len, v.as_mut_ptr().cast());
-            v.set_len(log_len.try_into().unwrap());
-            panic!("Program Link Error: {}", String::from_utf8_lossy(&v));
+            let mut log_len = 0;
+            GetProgramiv(program, INFO_LOG_LENGTH, &mut log_len);
+            let mut v: Vec<u8> = vec![0; log_len.max(1) as usize];
+            GetProgramInfoLog(program, v.len() as GLsizei, &mut log_len, v.as_mut_ptr().cast());
+            v.truncate(log_len as usize);
+            DeleteProgram(program);
+            return Err(ShaderError::Link {
+                vs_path: vs_path.to_path_buf(),
+                fs_path: fs_path.to_path_buf(),
+                log: String::from_utf8_lossy(&v).into_owned(),
+            });
         }
         UseProgram(program);
-        DeleteShader(vs);
-        DeleteShader(fs);
-        Self {
-            vs: vs,
-            fs: fs,
-            program: program,
+        Ok(Self {
+            program,
+            vs_path: vs_path.to_path_buf(),
+            fs_path: fs_path.to_path_buf(),
+            defines,
+            sources,
+            locations: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Method to recompile the program from its shader files with the same defines
+    /// On failure the old program is kept and the error is returned
+    ///
+    /// # Safety
+    /// Requires a current OpenGL context on this thread.
+    pub unsafe fn reload(&mut self) -> Result<(), ShaderError> {
+        let defines: Vec<(&str, String)> = self
+            .defines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.clone()))
+            .collect();
+        let new = Self::with_defines(&self.vs_path, &self.fs_path, &defines)?;
+        DeleteProgram(self.program);
+        *self = new;
+        Ok(())
+    }
+
+    // Method to make this the active program
+    pub fn use_program(&self) {
+        unsafe { UseProgram(self.program) }
+    }
+
+    // Method to look up a uniform location, cached after the first query
+    // Returns -1 for uniforms that don't exist (or were optimised out), which GL ignores
+    pub fn location(&self, name: &str) -> GLint {
+        if let Some(&loc) = self.locations.borrow().get(name) {
+            return loc;
         }
+        let c_name = CString::new(name).expect("Uniform name contains a nul byte");
+        let loc = unsafe { GetUniformLocation(self.program, c_name.as_ptr()) };
+        self.locations.borrow_mut().insert(String::from(name), loc);
+        loc
     }
 
-    // Method that sends a uniform mat4f into the shader program
-    pub unsafe fn set_mat4f(&self, loc: &[u8], value: *const GLfloat) {
-        let loc = gl::GetUniformLocation(self.program, loc.as_ptr() as *const i8);
-        gl::UniformMatrix4fv(loc, 1, gl::FALSE, value);
+    // Method that sends a uniform mat4 into the shader program
+    pub fn set_mat4(&self, name: &str, value: &Mat4) {
+        unsafe { UniformMatrix4fv(self.location(name), 1, FALSE, value.as_ref().as_ptr()) }
+    }
+    // Method to send a uniform vec3 into the shader program
+    pub fn set_vec3(&self, name: &str, value: Vec3A) {
+        unsafe { Uniform3f(self.location(name), value.x, value.y, value.z) }
+    }
+    // Method that sends an array of vec3 into the shader program
+    pub fn set_vec3_array(&self, name: &str, values: &[Vec3]) {
+        unsafe { Uniform3fv(self.location(name), values.len() as GLsizei, values.as_ptr().cast()) }
+    }
+    // Method that sends a uniform float into the shader program
+    pub fn set_float(&self, name: &str, value: f32) {
+        unsafe { Uniform1f(self.location(name), value) }
+    }
+    // Method that sends an array of floats into the shader program
+    pub fn set_float_array(&self, name: &str, values: &[f32]) {
+        unsafe { Uniform1fv(self.location(name), values.len() as GLsizei, values.as_ptr()) }
     }
-    // Method to send a uniform vec3f into the shader program
-    pub unsafe fn set_vec3f(&self, loc: &[u8], v0: GLfloat, v1: GLfloat, v2: GLfloat) {
-        let loc = gl::GetUniformLocation(self.program, loc.as_ptr() as *const i8);
-        gl::Uniform3f(loc, v0, v1, v2);
+    // Method that sends a uniform int (or bool) into the shader program
+    pub fn set_int(&self, name: &str, value: i32) {
+        unsafe { Uniform1i(self.location(name), value) }
     }
-    // Method that sends an array of vec3f into the shader program
-    pub unsafe fn set_vec3fv(&self, loc: &[u8], count: usize, value: *const GLfloat) {
-        let loc = gl::GetUniformLocation(self.program, loc.as_ptr() as *const i8);
-        gl::Uniform3fv(loc, count as GLsizei, value);
+    // Method that binds a sampler uniform to a texture unit (0 for GL_TEXTURE0, ...)
+    pub fn set_sampler(&self, name: &str, unit: u32) {
+        unsafe { Uniform1i(self.location(name), unit as GLint) }
+    }
+}
+
+// Helper to read a shader file, resolving `#include "file"` (relative to the including file)
+// and injecting defines after the `#version` line. Each file is included at most once.
+fn preprocess(
+    path: &Path,
+    defines: &[(String, String)],
+    sources: &mut Vec<PathBuf>,
+) -> Result<String, ShaderError> {
+    let mut included = vec!();
+    let body = include(path, &mut included)?;
+    for file in included {
+        if !sources.contains(&file) {
+            sources.push(file);
+        }
+    }
+
+    let define_lines: String = defines
+        .iter()
+        .map(|(name, value)| format!("#define {} {}\n", name, value))
+        .collect();
+    let mut out = String::new();
+    let mut injected = false;
+    for line in body.lines() {
+        out.push_str(line);
+        out.push('\n');
+        if !injected && line.trim_start().starts_with("#version") {
+            out.push_str(&define_lines);
+            injected = true;
+        }
+    }
+    if !injected {
+        out.insert_str(0, &define_lines);
+    }
+    Ok(out)
+}
+
+// Helper for preprocess, recursively expanding #include directives
+fn include(path: &Path, included: &mut Vec<PathBuf>) -> Result<String, ShaderError> {
+    included.push(path.to_path_buf());
+    let source = std::fs::read_to_string(path).map_err(|source| ShaderError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let mut out = String::new();
+    for (i, line) in source.lines().enumerate() {
+        let Some(rest) = line.trim_start().strip_prefix("#include") else {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        };
+        let file = rest.trim().trim_matches(|c| c == '"' || c == '<' || c == '>');
+        if file.is_empty() {
+            return Err(ShaderError::Include {
+                path: path.to_path_buf(),
+                line: i + 1,
+                reason: String::from("expected #include \"file\""),
+            });
+        }
+        let file = path.parent().unwrap_or(Path::new("")).join(file);
+        if included.contains(&file) {
+            continue;
+        }
+        if !file.exists() {
+            return Err(ShaderError::Include {
+                path: path.to_path_buf(),
+                line: i + 1,
+                reason: format!("{} not found", file.display()),
+            });
+        }
+        out.push_str(&include(&file, included)?);
+    }
+    Ok(out)
+}
+
+// Helper to compile a single shader stage, returning the full info log on failure
+unsafe fn compile(kind: GLenum, path: &Path, source: &str) -> Result<GLuint, ShaderError> {
+    let stage = if kind == VERTEX_SHADER { "Vertex" } else { "Fragment" };
+    let shader = CreateShader(kind);
+    ShaderSource(
+        shader,
+        1,
+        &(source.as_bytes().as_ptr().cast()),
+        &(source.len().try_into().unwrap()),
+    );
+    CompileShader(shader);
+    let mut success = 0;
+    GetShaderiv(shader, COMPILE_STATUS, &mut success);
+    if success == 0 {
+        let mut log_len = 0;
+        GetShaderiv(shader, INFO_LOG_LENGTH, &mut log_len);
+        let mut v: Vec<u8> = vec![0; log_len.max(1) as usize];
+        GetShaderInfoLog(shader, v.len() as GLsizei, &mut log_len, v.as_mut_ptr().cast());
+        v.truncate(log_len as usize);
+        DeleteShader(shader);
+        return Err(ShaderError::Compile {
+            stage,
+            path: path.to_path_buf(),
+            log: String::from_utf8_lossy(&v).into_owned(),
+        });
     }
+    Ok(shader)
 }
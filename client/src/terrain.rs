@@ -0,0 +1,240 @@
+use crate::assets::{AssetError, Assets};
+use crate::entities::Entity;
+use crate::meshloader::Mesh;
+use crate::renderer::{Backend, DrawItem, ProgramHandle, Renderer};
+use glam::{Vec3A, Vec3Swizzles};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// Terrain chunk, a square of the ground in full and low detail
+struct Chunk {
+    full: Rc<Mesh>,
+    full_vertices: HashMap<usize, usize>, // ground vertex -> vertex of `full`
+    low: Option<Rc<Mesh>>,                // None if no low poly faces fall in this chunk
+}
+
+// Chunked terrain for drawing the ground
+// The full resolution ground entity is still used for collision and mutation, mutations are
+// mirrored into the chunks. Each chunk is drawn from the low poly mesh once it is further than
+// lod_distance from the camera.
+pub struct Terrain {
+    pub ground: Entity,
+    pub lod_distance: f32,
+    low_mesh_name: String,
+    chunks_per_side: usize,
+    chunks: Vec<Chunk>,
+    low_links: HashMap<usize, Vec<(usize, usize)>>, // ground vertex -> (chunk, low poly vertex)
+}
+
+// Terrain methods
+impl Terrain {
+
+    // Terrain constructor, splitting ground and the low poly mesh into a grid of chunks
+    // The low poly mesh must cover the same area as the ground mesh in object space
+    pub fn new(
+        assets: &mut Assets,
+        ground: Entity,
+        low_mesh_name: &str,
+        chunks_per_side: usize,
+        lod_distance: f32,
+    ) -> Result<Self, AssetError> {
+        let mut terrain = Terrain {
+            ground,
+            lod_distance,
+            low_mesh_name: String::from(low_mesh_name),
+            chunks_per_side: chunks_per_side.max(1),
+            chunks: vec!(),
+            low_links: HashMap::new(),
+        };
+        terrain.build_chunks(assets)?;
+        Ok(terrain)
+    }
+
+    // Helper to (re)build the chunks from the current ground and low poly meshes
+    fn build_chunks(&mut self, assets: &mut Assets) -> Result<(), AssetError> {
+        let full = self.ground.mesh.clone();
+        let low = assets.mesh(&self.low_mesh_name)?;
+        let n = self.chunks_per_side;
+
+        // chunk grid over the object space xz extent of the ground
+        let min = full.vertices.iter().fold(Vec3A::splat(f32::MAX), |a, &v| a.min(v)).xz();
+        let max = full.vertices.iter().fold(Vec3A::splat(f32::MIN), |a, &v| a.max(v)).xz();
+        let size = (max - min).max(glam::Vec2::splat(f32::EPSILON));
+        let chunk_of = |mesh: &Mesh| -> Vec<Vec<usize>> {
+            let mut faces = vec![vec!(); n * n];
+            for (i, face) in mesh.faces.iter().enumerate() {
+                let centroid = face.vertices.iter().map(|&v| mesh.vertices[v]).sum::<Vec3A>() / 3.0;
+                let cell = ((centroid.xz() - min) / size * n as f32).floor();
+                let (x, z) = (
+                    (cell.x.max(0.0) as usize).min(n - 1),
+                    (cell.y.max(0.0) as usize).min(n - 1),
+                );
+                faces[z * n + x].push(i);
+            }
+            faces
+        };
+
+        self.chunks.clear();
+        self.low_links.clear();
+        for (full_faces, low_faces) in chunk_of(&full).into_iter().zip(chunk_of(&low)) {
+            if full_faces.is_empty() {
+                continue;
+            }
+            let (full_chunk, full_parents) = full.submesh(&full_faces);
+            let full_vertices: HashMap<usize, usize> =
+                full_parents.iter().enumerate().map(|(local, &g)| (g, local)).collect();
+
+            let low_chunk = if low_faces.is_empty() {
+                None
+            } else {
+                let (mut low_chunk, _) = low.submesh(&low_faces);
+                low_chunk.set_uv_mode(full.uv_mode);
+                // follow ground edits through the nearest full resolution vertex of the chunk
+                for (lv, &lpos) in low_chunk.vertices.iter().enumerate() {
+                    let nearest = full_parents
+                        .iter()
+                        .copied()
+                        .min_by(|&a, &b| {
+                            let da = full.vertices[a].xz().distance_squared(lpos.xz());
+                            let db = full.vertices[b].xz().distance_squared(lpos.xz());
+                            da.total_cmp(&db)
+                        })
+                        .unwrap();
+                    self.low_links.entry(nearest).or_default().push((self.chunks.len(), lv));
+                }
+                Some(Rc::new(low_chunk))
+            };
+            self.chunks.push(Chunk {
+                full: Rc::new(full_chunk),
+                full_vertices,
+                low: low_chunk,
+            });
+        }
+        Ok(())
+    }
+
+    // Method to mutate a ground vertex by a world space direction and amount, keeping the
+    // collision mesh and every chunk that uses the vertex in step
+    pub fn mutate(&mut self, idx: usize, dir: Vec3A, amount: f32) {
+        self.ground.mutate(idx, dir, amount);
+        let local_dir = self.ground.local_dir(dir);
+        for chunk in &mut self.chunks {
+            if let Some(&local) = chunk.full_vertices.get(&idx) {
+                Rc::make_mut(&mut chunk.full).mutate(local, local_dir, amount);
+            }
+        }
+        if let Some(links) = self.low_links.get(&idx) {
+            for &(c, lv) in links {
+                if let Some(low) = &mut self.chunks[c].low {
+                    Rc::make_mut(low).mutate(lv, local_dir, amount);
+                }
+            }
+        }
+    }
+
+    // Method to swap in a hot reloaded ground or low poly mesh, rebuilding the chunks
+    // Once the ground has been deformed the reload waits for the next reset, so this client keeps
+    // the same terrain as the others for the rest of the round
+    pub fn reload_mesh(&mut self, assets: &mut Assets, changed: &[PathBuf]) -> Result<(), AssetError> {
+        let low_path = assets.resolve(&self.low_mesh_name)?;
+        let ground_path = PathBuf::from(&self.ground.mesh.path);
+        if !changed.contains(&low_path) && !changed.contains(&ground_path) {
+            return Ok(());
+        }
+        if self.ground.mesh.deformed() {
+            println!("Ground has been deformed, the reloaded terrain is used from the next round");
+            return Ok(());
+        }
+        self.ground.reload_mesh(assets, changed);
+        self.build_chunks(assets)
+    }
+
+    // Method to undo every ground edit, going back to the ground mesh as it was loaded
+    pub fn reset(&mut self, assets: &mut Assets) -> Result<(), AssetError> {
+        if let Some(mesh) = assets.cached_mesh(std::path::Path::new(&self.ground.mesh.path)) {
+            self.ground.set_mesh(mesh);
+        }
+        self.build_chunks(assets)
+    }
+
+    // Method to build the draw items for the visible level of detail of every chunk
+    pub fn draw_items<B: Backend>(
+        &self,
+        renderer: &mut Renderer<B>,
+        program: ProgramHandle,
+        eye: Vec3A,
+    ) -> Vec<DrawItem> {
+        let transform = self.ground.model_mat();
+        self.chunks
+            .iter()
+            .map(|chunk| {
+                let bounds = chunk.full.bounds.transformed(&transform);
+                let far = bounds.center.distance(eye) - bounds.radius > self.lod_distance;
+                let mesh = match (&chunk.low, far) {
+                    (Some(low), true) => low,
+                    _ => &chunk.full,
+                };
+                DrawItem {
+                    mesh: renderer.mesh(mesh),
+                    program,
+                    material: self.ground.material,
+                    transform,
+                    bounds: Some(bounds),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::{Call, FrameUniforms, RecordingBackend};
+    use glam::Mat4;
+
+    // Helper to build the default terrain from the workspace assets
+    fn terrain(assets: &mut Assets) -> Terrain {
+        let ground = Entity::new(assets, "mesh/ground.stl", Vec3A::ZERO, Vec3A::ONE, 0.0).unwrap();
+        Terrain::new(assets, ground, "mesh/ground_lowpoly.stl", 4, f32::MAX).unwrap()
+    }
+
+    #[test]
+    fn chunk_edits_reupload_the_same_handle() {
+        let mut assets = Assets::with_root(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../assets"));
+        let mut terrain = terrain(&mut assets);
+        let mut renderer = Renderer::new(RecordingBackend::default());
+        let handles = |t: &Terrain, r: &mut Renderer<RecordingBackend>| {
+            t.draw_items(r, ProgramHandle(0), Vec3A::ZERO).iter().map(|i| i.mesh).collect::<Vec<_>>()
+        };
+        let before = handles(&terrain, &mut renderer);
+        let uploaded = renderer.backend.calls.len();
+
+        terrain.mutate(0, Vec3A::Y, 0.5);
+        assert_eq!(handles(&terrain, &mut renderer), before);
+        renderer.render(&FrameUniforms {
+            proj: Mat4::IDENTITY,
+            view: Mat4::IDENTITY,
+            view_pos: Vec3A::ZERO,
+            point_lights: vec!(),
+            sun_direction: Vec3A::NEG_Y,
+            sun_color: Vec3A::ONE,
+            light_space: None,
+            fog_color: Vec3A::ZERO,
+            fog_density: 0.0,
+            skybox: None,
+        });
+
+        let calls = &renderer.backend.calls[uploaded..];
+        let reuploads: Vec<usize> = calls
+            .iter()
+            .filter_map(|c| match c {
+                Call::UploadMesh { mesh, .. } => Some(*mesh),
+                _ => None,
+            })
+            .collect();
+        assert!(!reuploads.is_empty());
+        assert!(reuploads.iter().all(|&m| m <= before.len()));
+        assert!(!calls.iter().any(|c| matches!(c, Call::DeleteMesh(_))));
+    }
+}
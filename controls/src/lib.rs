@@ -0,0 +1,360 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// Environment variable that overrides the bindings file location
+pub const BINDINGS_ENV_VAR: &str = "SEPHERE_BINDINGS";
+const CONFIG_DIR: &str = "se-phere";
+const BINDINGS_FILE: &str = "bindings.cfg";
+
+// Game action that inputs can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Forward,
+    Backward,
+    SpinLeft,
+    SpinRight,
+    TurnLeft,  // orbits the camera even while standing still
+    TurnRight,
+    TiltUp,
+    TiltDown,
+    Jump,
+    FlyUp, // moves the spectator camera, which flies instead of rolling
+    FlyDown,
+    RaiseGround,
+    LowerGround,
+    ZoomIn,
+    ZoomOut,
+    CursorUp, // moves the virtual cursor that picks the ground to edit without a mouse
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    MouseLook,       // held to look around with the mouse
+    ToggleMouseLook, // captures or releases the cursor for mouse look
+    CycleCamera,
+    Ready, // ready up for the next round
+    Fullscreen,
+    Quit,
+}
+
+impl Action {
+    // Every action, in the order they are listed in the bindings file and the launcher
+    pub const ALL: [Action; 25] = [
+        Action::Forward,
+        Action::Backward,
+        Action::SpinLeft,
+        Action::SpinRight,
+        Action::TurnLeft,
+        Action::TurnRight,
+        Action::TiltUp,
+        Action::TiltDown,
+        Action::Jump,
+        Action::FlyUp,
+        Action::FlyDown,
+        Action::RaiseGround,
+        Action::LowerGround,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::CursorUp,
+        Action::CursorDown,
+        Action::CursorLeft,
+        Action::CursorRight,
+        Action::MouseLook,
+        Action::ToggleMouseLook,
+        Action::CycleCamera,
+        Action::Ready,
+        Action::Fullscreen,
+        Action::Quit,
+    ];
+
+    // Getter for the action's key in the bindings file
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Forward => "forward",
+            Action::Backward => "backward",
+            Action::SpinLeft => "spin_left",
+            Action::SpinRight => "spin_right",
+            Action::TurnLeft => "turn_left",
+            Action::TurnRight => "turn_right",
+            Action::TiltUp => "tilt_up",
+            Action::TiltDown => "tilt_down",
+            Action::Jump => "jump",
+            Action::FlyUp => "fly_up",
+            Action::FlyDown => "fly_down",
+            Action::RaiseGround => "raise_ground",
+            Action::LowerGround => "lower_ground",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::CursorUp => "cursor_up",
+            Action::CursorDown => "cursor_down",
+            Action::CursorLeft => "cursor_left",
+            Action::CursorRight => "cursor_right",
+            Action::MouseLook => "mouse_look",
+            Action::ToggleMouseLook => "toggle_mouse_look",
+            Action::CycleCamera => "cycle_camera",
+            Action::Ready => "ready",
+            Action::Fullscreen => "fullscreen",
+            Action::Quit => "quit",
+        }
+    }
+
+    // Getter for the action's description shown to players
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Forward => "Roll Forward",
+            Action::Backward => "Roll Backward",
+            Action::SpinLeft => "Spin Left",
+            Action::SpinRight => "Spin Right",
+            Action::TurnLeft => "Turn Camera Left",
+            Action::TurnRight => "Turn Camera Right",
+            Action::TiltUp => "Tilt Camera Up",
+            Action::TiltDown => "Tilt Camera Down",
+            Action::Jump => "Jump",
+            Action::FlyUp => "Fly Up (Spectating)",
+            Action::FlyDown => "Fly Down (Spectating)",
+            Action::RaiseGround => "Raise Ground",
+            Action::LowerGround => "Lower Ground",
+            Action::ZoomIn => "Zoom Camera In",
+            Action::ZoomOut => "Zoom Camera Out",
+            Action::CursorUp => "Move Cursor Up",
+            Action::CursorDown => "Move Cursor Down",
+            Action::CursorLeft => "Move Cursor Left",
+            Action::CursorRight => "Move Cursor Right",
+            Action::MouseLook => "Hold to Look Around",
+            Action::ToggleMouseLook => "Toggle Mouse Look",
+            Action::CycleCamera => "Cycle Camera Mode",
+            Action::Ready => "Ready Up",
+            Action::Fullscreen => "Toggle Fullscreen",
+            Action::Quit => "Quit",
+        }
+    }
+
+    // Getter for the inputs bound to the action by default
+    pub fn default_inputs(self) -> &'static [&'static str] {
+        match self {
+            Action::Forward => &["W", "PadLeftStickUp"],
+            Action::Backward => &["S", "PadLeftStickDown"],
+            Action::SpinLeft => &["A", "PadLeftStickLeft"],
+            Action::SpinRight => &["D", "PadLeftStickRight"],
+            Action::TurnLeft => &["Q", "PadRightStickLeft"],
+            Action::TurnRight => &["E", "PadRightStickRight"],
+            Action::TiltUp => &["I", "PadRightStickUp"],
+            Action::TiltDown => &["K", "PadRightStickDown"],
+            Action::Jump => &["Space", "PadA"],
+            Action::FlyUp => &["Space", "PadA"],
+            Action::FlyDown => &["LeftControl", "PadB"],
+            Action::RaiseGround => &["MouseLeft", "PadRightTrigger"],
+            Action::LowerGround => &["MouseRight", "PadLeftTrigger"],
+            Action::ZoomIn => &["ScrollUp", "PadRightBumper"],
+            Action::ZoomOut => &["ScrollDown", "PadLeftBumper"],
+            Action::CursorUp => &["PadDpadUp"],
+            Action::CursorDown => &["PadDpadDown"],
+            Action::CursorLeft => &["PadDpadLeft"],
+            Action::CursorRight => &["PadDpadRight"],
+            Action::MouseLook => &["MouseMiddle"],
+            Action::ToggleMouseLook => &["M"],
+            Action::CycleCamera => &["C", "PadY"],
+            Action::Ready => &["R", "PadStart"],
+            Action::Fullscreen => &["F"],
+            Action::Quit => &["Escape"],
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        Action::ALL.into_iter().find(|a| a.name() == s).ok_or(())
+    }
+}
+
+// Names of every input that can be bound
+// Keys are named as egui names them (egui::Key::name) so the launcher can record them directly
+pub const INPUT_NAMES: &[&str] = &[
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M",
+    "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z",
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9",
+    "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12",
+    "Up", "Down", "Left", "Right",
+    "Space", "Escape", "Enter", "Tab", "Backspace", "Insert", "Delete", "Home", "End", "PageUp", "PageDown",
+    "Comma", "Minus", "Period", "Equals", "Semicolon", "Slash", "Backslash", "OpenBracket", "CloseBracket", "Backtick",
+    "LeftShift", "RightShift", "LeftControl", "RightControl", "LeftAlt", "RightAlt",
+    "MouseLeft", "MouseRight", "MouseMiddle", "Mouse4", "Mouse5", "ScrollUp", "ScrollDown",
+    // gamepad buttons, then each direction of the sticks and the triggers, which are analogue
+    "PadA", "PadB", "PadX", "PadY", "PadLeftBumper", "PadRightBumper", "PadBack", "PadStart", "PadGuide",
+    "PadLeftThumb", "PadRightThumb", "PadDpadUp", "PadDpadDown", "PadDpadLeft", "PadDpadRight",
+    "PadLeftStickUp", "PadLeftStickDown", "PadLeftStickLeft", "PadLeftStickRight",
+    "PadRightStickUp", "PadRightStickDown", "PadRightStickLeft", "PadRightStickRight",
+    "PadLeftTrigger", "PadRightTrigger",
+];
+
+// Error returned when a bindings file line can't be used
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindingError {
+    Syntax { line: usize },
+    UnknownAction { line: usize, name: String },
+    UnknownInput { line: usize, name: String },
+}
+
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindingError::Syntax { line } => write!(f, "line {}: expected `action = input, input`", line),
+            BindingError::UnknownAction { line, name } => write!(f, "line {}: unknown action `{}`", line, name),
+            BindingError::UnknownInput { line, name } => write!(f, "line {}: unknown input `{}`", line, name),
+        }
+    }
+}
+
+impl std::error::Error for BindingError {}
+
+// Inputs bound to each action, any number per action
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    inputs: Vec<Vec<String>>, // indexed like Action::ALL
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            inputs: Action::ALL
+                .iter()
+                .map(|a| a.default_inputs().iter().map(|i| String::from(*i)).collect())
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Bindings {
+    // Writes the bindings in the same format they are read in
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# se-phere controls, `action = input, input`")?;
+        for action in Action::ALL {
+            writeln!(f, "{} = {}", action.name(), self.get(action).join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Bindings {
+    // Method to load the bindings file, falling back to the defaults for actions it leaves out
+    // A default file is written on first run so there is something to edit. Bad lines are
+    // returned alongside the bindings rather than failing the whole file.
+    pub fn load() -> (Self, Vec<BindingError>) {
+        let Ok(text) = std::fs::read_to_string(bindings_path()) else {
+            let bindings = Bindings::default();
+            if let Err(e) = bindings.save() {
+                println!("Could not write default bindings to {}: {}", bindings_path().display(), e);
+            }
+            return (bindings, vec!());
+        };
+        Bindings::parse(&text)
+    }
+
+    // Method to read bindings from the text of a bindings file
+    pub fn parse(text: &str) -> (Self, Vec<BindingError>) {
+        let mut bindings = Bindings::default();
+        let mut errors = vec!();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((name, inputs)) = line.split_once('=') else {
+                errors.push(BindingError::Syntax { line: i + 1 });
+                continue;
+            };
+            let Ok(action) = name.trim().parse::<Action>() else {
+                errors.push(BindingError::UnknownAction {
+                    line: i + 1,
+                    name: String::from(name.trim()),
+                });
+                continue;
+            };
+            // an action listed with nothing after `=` is deliberately unbound
+            bindings.clear(action);
+            for input in inputs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                if !INPUT_NAMES.contains(&input) {
+                    errors.push(BindingError::UnknownInput {
+                        line: i + 1,
+                        name: String::from(input),
+                    });
+                    continue;
+                }
+                bindings.add(action, input);
+            }
+        }
+        (bindings, errors)
+    }
+
+    // Method to write the bindings file, creating the config directory if needed
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = bindings_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_string())
+    }
+
+    // Getter for the inputs bound to an action
+    pub fn get(&self, action: Action) -> &[String] {
+        &self.inputs[action as usize]
+    }
+
+    // Method to bind an input to an action, as well as anything it is already bound to
+    pub fn add(&mut self, action: Action, input: &str) {
+        let inputs = &mut self.inputs[action as usize];
+        if !inputs.iter().any(|i| i == input) {
+            inputs.push(String::from(input));
+        }
+    }
+
+    // Method to unbind an input from an action
+    pub fn remove(&mut self, action: Action, input: &str) {
+        self.inputs[action as usize].retain(|i| i != input);
+    }
+
+    // Method to unbind everything from an action
+    pub fn clear(&mut self, action: Action) {
+        self.inputs[action as usize].clear();
+    }
+
+    // Method to get every action an input is bound to
+    pub fn actions_for<'a>(&'a self, input: &'a str) -> impl Iterator<Item = Action> + 'a {
+        Action::ALL.into_iter().filter(move |a| self.get(*a).iter().any(|i| i == input))
+    }
+}
+
+// Helper to get the se-phere folder in the user config directory
+// ($XDG_CONFIG_HOME or ~/.config on unix, %APPDATA% on windows), None if there is no such folder
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join(CONFIG_DIR))
+}
+
+// Helper to get the bindings file path: SEPHERE_BINDINGS, else the user config directory, else
+// the working directory
+pub fn bindings_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(BINDINGS_ENV_VAR) {
+        return PathBuf::from(path);
+    }
+    match config_dir() {
+        Some(dir) => dir.join(BINDINGS_FILE),
+        None => PathBuf::from(BINDINGS_FILE),
+    }
+}
+
+// Helper to get the path of another workspace binary installed next to this executable,
+// falling back to the bare name so the OS searches PATH
+pub fn sibling_exe(name: &str) -> PathBuf {
+    let file = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+    std::env::current_exe()
+        .ok()
+        .map(|exe| exe.with_file_name(&file))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(file))
+}
@@ -17,8 +17,27 @@ pub enum Command {
     RGNDSTATE, // 0x08
     LOGIN,     // 0x09
     SETPID,    // 0x0A
+    GOAL,      // 0x0B
+    DEATH,     // 0x0C
+    MATCHEND,  // 0x0D
+    READY,     // 0x0E
+    PHASE,     // 0x0F
 }
 
+// Enum to represent the phases of a match, the server moves every client through them together
+#[derive(FromRepr, Debug, PartialEq, Eq, Copy, Clone)]
+#[repr(u8)]
+pub enum Phase {
+    Waiting,   // not enough players yet
+    Ready,     // waiting for every player to ready up
+    Countdown, // everyone is ready, the round is about to start
+    Playing,
+    Results,   // round over, showing the winner before a rematch
+}
+
+// Player id meaning no player, e.g. when a match ends without a winner
+pub const NO_PLAYER: u8 = 255;
+
 // Message struct storing command and payload
 #[derive(Debug)]
 pub struct Message {
@@ -94,12 +113,12 @@ impl Message {
     // Returns Some(Message) if bytes are valid
     // Otherwise returns None
     pub fn try_from_data(_socket_addr: SocketAddr, data: &[u8]) -> Option<Self> {
-        let command = Command::from_repr(*data.get(0)?);
+        let command = Command::from_repr(*data.first()?);
         let mut payload = vec![];
         payload.extend_from_slice(data.get(1..)?);
         Some(Message {
             command: command?,
-            payload: payload,
+            payload,
         })
     }
 
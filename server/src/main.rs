@@ -1,28 +1,197 @@
 use glam::*;
-use messaging::{AsBytes, Command, Message};
+use messaging::{AsBytes, Command, Message, Phase, NO_PLAYER};
 use std::error::Error;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 use std::{env, io};
 use tokio::net::UdpSocket;
 
 const MUTATION_STACK: usize = 1024; //max for windows is 20199, empirically tested, may change
+const MAX_PLAYERS: usize = 64; // one bit each in the ready mask
+const DEFAULT_WIN_SCORE: u8 = 10;
+const DEFAULT_LIVES: u8 = 3;
+const DEFAULT_MIN_PLAYERS: u8 = 1;
+const COUNTDOWN_TIME: Duration = Duration::from_secs(3);
+const RESULTS_TIME: Duration = Duration::from_secs(8);
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5); // players not heard from for this long don't hold up the match
 
 // Some structs to keep game state
 #[derive(Debug, Copy, Clone)]
 struct Player {
+    #[allow(dead_code)] // not read yet, positions are sent in player id order
     pid: u8,
     pos: Vec3A,
 }
 
 struct Ground {
+    #[allow(dead_code)] // not read yet, see the commented out code in Command::MUT
     mutations: [f32; MUTATION_STACK],
     frame: u64,
 }
 
+// Score and lives of a player, owned by the server so every client agrees on them
+#[derive(Debug, Copy, Clone, Default)]
+struct Score {
+    points: u8,
+    lives: u8,
+}
+
+// Match rules, set from the command line
+#[derive(Debug, Copy, Clone)]
+struct Rules {
+    win_score: u8,   // goals needed to win
+    lives: u8,       // falls before a player is out
+    min_players: u8, // players needed before readying up
+}
+
 struct GameState {
-    players: [Option<Player>; 64],
+    players: [Option<Player>; MAX_PLAYERS],
     ground: Ground,
     num_players: u8,
+    scores: [Score; MAX_PLAYERS],
+    rules: Rules,
+    winner: Option<u8>, // set when the match is over, NO_PLAYER if everyone is out
+    phase: Phase,
+    phase_start: Instant,
+    round: u32,
+    ready: u64,                                // bit per player
+    last_seen: [Option<Instant>; MAX_PLAYERS], // when each player last asked for the game state
+}
+
+// GameState methods
+impl GameState {
+
+    // GameState constructor, for a server nobody has joined yet
+    fn new(rules: Rules) -> Self {
+        GameState {
+            players: [None; MAX_PLAYERS],
+            ground: Ground {
+                mutations: [0f32; MUTATION_STACK],
+                frame: 0u64,
+            },
+            num_players: 0u8,
+            scores: [Score::default(); MAX_PLAYERS],
+            rules,
+            winner: None,
+            phase: Phase::Waiting,
+            phase_start: Instant::now(),
+            round: 0,
+            ready: 0,
+            last_seen: [None; MAX_PLAYERS],
+        }
+    }
+
+    // Method to get the players that are still polling the server
+    fn connected(&self) -> Vec<u8> {
+        (0..self.num_players)
+            .filter(|p| match self.last_seen[*p as usize] {
+                Some(seen) => seen.elapsed() < CONNECTION_TIMEOUT,
+                None => false,
+            })
+            .collect()
+    }
+
+    // Method to get the connected players that still have lives left
+    fn alive(&self) -> Vec<u8> {
+        self.connected().into_iter().filter(|p| self.scores[*p as usize].lives > 0).collect()
+    }
+
+    // Method to check if every connected player is ready
+    fn all_ready(&self) -> bool {
+        self.connected().iter().all(|p| self.ready & (1 << p) != 0)
+    }
+
+    // Method to get the time left in a timed phase
+    fn remaining(&self) -> Duration {
+        let length = match self.phase {
+            Phase::Countdown => COUNTDOWN_TIME,
+            Phase::Results => RESULTS_TIME,
+            _ => Duration::ZERO,
+        };
+        length.saturating_sub(self.phase_start.elapsed())
+    }
+
+    // Method to start the next phase when the current one is done, returns the new phase if it changed
+    // Called after every message, which clients send many times a second, so timed phases end on time
+    fn advance(&mut self) -> Option<Phase> {
+        let enough = self.connected().len() >= self.rules.min_players as usize;
+        let next = match self.phase {
+            Phase::Waiting if enough => Phase::Ready,
+            Phase::Ready | Phase::Countdown if !enough => Phase::Waiting,
+            Phase::Ready if self.all_ready() => Phase::Countdown,
+            // someone changed their mind
+            Phase::Countdown if !self.all_ready() => Phase::Ready,
+            Phase::Countdown if self.remaining() == Duration::ZERO => Phase::Playing,
+            Phase::Playing if self.winner.is_some() => Phase::Results,
+            // players left mid round, so nobody may be around to report the deaths that end it
+            Phase::Playing if !enough || self.alive().is_empty() => Phase::Results,
+            Phase::Results if self.remaining() == Duration::ZERO => Phase::Ready,
+            _ => return None,
+        };
+        match next {
+            Phase::Playing => self.reset_scores(),
+            // the last player still in wins, otherwise the round is abandoned
+            Phase::Results if self.winner.is_none() => {
+                self.winner = match self.alive().as_slice() {
+                    [last] => Some(*last),
+                    _ => Some(NO_PLAYER),
+                };
+            }
+            // rematch, with fresh terrain and everyone readying up again
+            Phase::Ready if self.phase == Phase::Results => {
+                self.round += 1;
+                self.ground.frame = 0;
+                self.ready = 0;
+                self.reset_scores();
+            }
+            _ => {}
+        }
+        println!("Round {}: {:?} -> {:?}", self.round, self.phase, next);
+        self.phase = next;
+        self.phase_start = Instant::now();
+        Some(next)
+    }
+
+    // Helper to give every player their starting score and lives
+    fn reset_scores(&mut self) {
+        self.winner = None;
+        for score in &mut self.scores {
+            *score = Score {
+                points: 0,
+                lives: self.rules.lives,
+            };
+        }
+    }
+
+    // Method to count a player reaching the goal, returns the winner if it ends the match
+    fn goal(&mut self, pid: u8) -> Option<u8> {
+        let score = self.scores.get_mut(pid as usize)?;
+        if self.phase != Phase::Playing || self.winner.is_some() || score.lives == 0 {
+            return None;
+        }
+        score.points = score.points.saturating_add(1);
+        if score.points >= self.rules.win_score {
+            self.winner = Some(pid);
+        }
+        self.winner
+    }
+
+    // Method to count a player falling off the map, returns the winner if it ends the match
+    // The match ends when only one connected player is left, or nobody when playing alone
+    fn death(&mut self, pid: u8) -> Option<u8> {
+        let score = self.scores.get_mut(pid as usize)?;
+        if self.phase != Phase::Playing || self.winner.is_some() || score.lives == 0 {
+            return None;
+        }
+        score.lives -= 1;
+        let connected = self.connected();
+        match self.alive().as_slice() {
+            [] => self.winner = Some(NO_PLAYER),
+            [last] if connected.len() > 1 => self.winner = Some(*last),
+            _ => {}
+        }
+        self.winner
+    }
 }
 
 
@@ -47,8 +216,11 @@ impl Server {
         // Initialize vector to store sockets for each connection (used for LAN mode)
         let mut player_sockets = vec![];
 
+        // Address each player polls the game state from, where match results are sent
+        let mut peers: [Option<SocketAddr>; MAX_PLAYERS] = [None; MAX_PLAYERS];
+
         // Initialize array as a buffer for each player to store mutations as they come
-        let mut player_buffers: [Option<Vec<u8>>; 32] = Default::default(); // Initializes to [None; 32]
+        let mut player_buffers: [Option<Vec<u8>>; MAX_PLAYERS] = std::array::from_fn(|_| None);
         loop {
             // First we check to see if there's a message we need to echo back.
             // If so then we try to send it back to the original source, waiting
@@ -61,29 +233,63 @@ impl Server {
                     
                     // Pattern matching on Command enum sent by client
                     match m.command {
-                        // Client login message. Action: Assign player id to them.
+                        // Client login message. Action: Assign player id to them, or give a
+                        // restarted client its old id back. Ignored once the server is full.
                         Command::LOGIN => {
-                            let mut reply = Message::new(Command::SETPID);
-                            reply.push_bytes((player_sockets.len() as u8).as_bytes());
                             if let Some(port) = m.extract_u32(0) {
                                 let ls = SocketAddr::new(peer.ip(), port as u16);
-                                player_sockets.push(ls);
-                                socket.send_to(&reply.get_bytes(), &peer).await?;
-                                self.state.num_players += 1;
+                                let known = player_sockets.iter().position(|s| *s == ls);
+                                let pid = match known {
+                                    Some(pid) => Some(pid),
+                                    None if player_sockets.len() < MAX_PLAYERS => {
+                                        let pid = player_sockets.len();
+                                        player_sockets.push(ls);
+                                        if let Some(score) = self.state.scores.get_mut(pid) {
+                                            *score = Score {
+                                                points: 0,
+                                                lives: self.state.rules.lives,
+                                            };
+                                        }
+                                        self.state.num_players += 1;
+                                        Some(pid)
+                                    }
+                                    None => None,
+                                };
+                                if let Some(pid) = pid {
+                                    let mut reply = Message::new(Command::SETPID);
+                                    reply.push_bytes((pid as u8).as_bytes());
+                                    reply.push_bytes(self.state.rules.lives.as_bytes());
+                                    reply.push_bytes(self.state.rules.win_score.as_bytes());
+                                    socket.send_to(&reply.get_bytes(), &peer).await?;
+                                } else {
+                                    println!("Server full, ignoring login from {}", peer);
+                                }
                             }
                             dbg!(&player_sockets);
                         }
+
                         // Reserved for future use, sending large binary data
                         Command::BLOB => {}
 
-                        // Client game state request message. Action: Send number of players and
-                        // ground frame number
+                        // Client game state request message. Action: Send number of players,
+                        // ground frame number, match phase, winner, who is ready and every score
                         Command::STATE => {
-                            if let Some(_pid) = m.extract_u8(0) {
+                            if let Some(pid) = m.extract_u8(0) {
                                 //println!("PID {} wants the gamestate", pid);
+                                if let Some(p) = peers.get_mut(pid as usize) {
+                                    *p = Some(peer);
+                                    self.state.last_seen[pid as usize] = Some(Instant::now());
+                                }
                                 let mut reply = Message::new(Command::RSTATE);
                                 reply.push_bytes(self.state.num_players.as_bytes());
                                 reply.push_bytes(self.state.ground.frame.as_bytes());
+                                reply.push_bytes(phase_bytes(&self.state));
+                                reply.push_bytes(self.state.winner.unwrap_or(NO_PLAYER).as_bytes());
+                                reply.push_bytes(self.state.ready.as_bytes());
+                                for score in &self.state.scores[..self.state.num_players as usize] {
+                                    reply.push_bytes(score.points.as_bytes());
+                                    reply.push_bytes(score.lives.as_bytes());
+                                }
                                 socket.send_to(&reply.get_bytes(), &peer).await?;
                                 /*
                                 if let Some(v) = &player_buffers[pid as usize] {
@@ -117,7 +323,48 @@ impl Server {
                             }
                         }
 
+                        // Client goal and death messages. Action: Update the player's score, the match
+                        // ends once someone has won
+                        // Only accepted from the socket the player logged in with
+                        Command::GOAL | Command::DEATH => {
+                            let owned = |p: &u8| player_sockets.get(*p as usize) == Some(&peer);
+                            if let Some(pid) = m.extract_u8(0).filter(owned) {
+                                let winner = match m.command {
+                                    Command::GOAL => self.state.goal(pid),
+                                    _ => self.state.death(pid),
+                                };
+                                if let Some(winner) = winner {
+                                    println!("Match over, winner: {}", winner);
+                                }
+                            } else {
+                                println!(
+                                    "Invalid payload for command: {:?} (0x{:02x})",
+                                    m.command, b[0]
+                                );
+                            }
+                        }
+
+                        // Client ready message. Action: Mark the player ready or not for the next round
+                        // Only accepted from the socket the player logged in with
+                        Command::READY => {
+                            let owned = |p: &u8| player_sockets.get(*p as usize) == Some(&peer);
+                            if let (Some(pid), Some(ready)) = (m.extract_u8(0).filter(owned), m.extract_u8(1)) {
+                                match ready {
+                                    0 => self.state.ready &= !(1 << pid),
+                                    _ => self.state.ready |= 1 << pid,
+                                }
+                            } else {
+                                println!(
+                                    "Invalid payload for command: {:?} (0x{:02x})",
+                                    m.command, b[0]
+                                );
+                            }
+                        }
+
                         // Client ground mutation message. Action: Store mutations in player buffers
+                        // Ground can only be changed during a round
+                        Command::MUT if self.state.phase != Phase::Playing => {}
+
                         Command::MUT => {
                             if let Some(_amt) = m.extract_f32(5) {
                                 // let pid = m.extract_u8(0).unwrap();
@@ -167,9 +414,8 @@ impl Server {
                         // data about ground deformations.
                         Command::GNDSTATE => {
                             if let Some(pid) = m.extract_u8(0) {
-                                if let Some(v) = &player_buffers[pid as usize] {
+                                if let Some(v) = player_buffers.get_mut(pid as usize).and_then(Option::take) {
                                     socket.send_to(v.as_slice(), &peer).await?;
-                                    player_buffers[pid as usize] = None;
                                 } else {
                                     //socket.send_to(&[], &peer).await?;
                                 }
@@ -190,6 +436,30 @@ impl Server {
                 //println!();
             }
 
+            // Move the match along and tell every player about the new phase, and the result
+            // when the match is over
+            let round = self.state.round;
+            if let Some(phase) = self.state.advance() {
+                if phase == Phase::Results {
+                    let mut end = Message::new(Command::MATCHEND);
+                    end.push_bytes(self.state.winner.unwrap_or(NO_PLAYER).as_bytes());
+                    let data = end.get_bytes();
+                    for p in peers.iter().flatten() {
+                        socket.send_to(&data, p).await?;
+                    }
+                }
+                if self.state.round != round {
+                    // terrain is reset for a rematch, so pending mutations are dropped
+                    player_buffers = std::array::from_fn(|_| None);
+                }
+                let mut m = Message::new(Command::PHASE);
+                m.push_bytes(phase_bytes(&self.state));
+                let data = m.get_bytes();
+                for p in peers.iter().flatten() {
+                    socket.send_to(&data, p).await?;
+                }
+            }
+
             // If we're here then `to_send` is `None`, so we take a look for the
             // next message we're going to echo back.
             to_send = Some(socket.recv_from(&mut buf).await?);
@@ -199,28 +469,146 @@ impl Server {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let addr = env::args()
-        .nth(1)
-        .unwrap_or_else(|| "0.0.0.0:42069".to_string());
+    // server [address] [--win-score N] [--lives N] [--min-players N]
+    let mut addr = "0.0.0.0:42069".to_string();
+    let mut rules = Rules {
+        win_score: DEFAULT_WIN_SCORE,
+        lives: DEFAULT_LIVES,
+        min_players: DEFAULT_MIN_PLAYERS,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--win-score" => rules.win_score = parse_count(&arg, args.next())?,
+            "--lives" => rules.lives = parse_count(&arg, args.next())?,
+            "--min-players" => rules.min_players = parse_count(&arg, args.next())?,
+            _ => addr = arg,
+        }
+    }
 
     let socket = UdpSocket::bind(&addr).await?;
     println!("Listening on: {}", socket.local_addr()?);
+    println!(
+        "First to {} goals wins, {} lives each, {} players needed to start",
+        rules.win_score, rules.lives, rules.min_players
+    );
 
     let server = Server {
         socket,
         buf: vec![0; 1024],
         to_send: None,
-        state: GameState {
-            players: [None; 64],
-            ground: Ground {
-                mutations: [0f32; MUTATION_STACK],
-                frame: 0u64,
-            },
-            num_players: 0u8,
-        },
+        state: GameState::new(rules),
     };
 
     // This starts the server task.
     server.run().await?;
     Ok(())
 }
+
+// Helper to get the phase, round and time left in the phase as they are sent to clients
+fn phase_bytes(state: &GameState) -> Vec<u8> {
+    let mut bytes = (state.phase as u8).as_bytes();
+    bytes.extend(state.round.as_bytes());
+    bytes.extend((state.remaining().as_millis() as u32).as_bytes());
+    bytes
+}
+
+// Helper to parse the value of a count flag, which must be at least 1
+fn parse_count(flag: &str, value: Option<String>) -> Result<u8, String> {
+    value
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .ok_or(format!("{} expects a number from 1 to 255", flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Helper to build a match of connected players in the Ready phase
+    fn joined(players: u8, win_score: u8, lives: u8) -> GameState {
+        let mut state = GameState::new(Rules {
+            win_score,
+            lives,
+            min_players: 1,
+        });
+        state.num_players = players;
+        for seen in &mut state.last_seen[..players as usize] {
+            *seen = Some(Instant::now());
+        }
+        assert_eq!(state.advance(), Some(Phase::Ready));
+        state
+    }
+
+    // Helper to build a match of connected players in the Playing phase
+    fn playing(players: u8, win_score: u8, lives: u8) -> GameState {
+        let mut state = joined(players, win_score, lives);
+        state.ready = (1 << players) - 1;
+        assert_eq!(state.advance(), Some(Phase::Countdown));
+        finish_phase(&mut state);
+        assert_eq!(state.advance(), Some(Phase::Playing));
+        state
+    }
+
+    // Helper to end a timed phase without waiting for it
+    fn finish_phase(state: &mut GameState) {
+        state.phase_start -= COUNTDOWN_TIME.max(RESULTS_TIME);
+    }
+
+    #[test]
+    fn reaching_win_score_wins() {
+        let mut state = playing(2, 2, 3);
+        assert_eq!(state.goal(0), None);
+        assert_eq!(state.goal(1), None);
+        assert_eq!(state.goal(0), Some(0));
+        // the match is over, later goals don't count
+        assert_eq!(state.goal(1), None);
+        assert_eq!(state.scores[1].points, 1);
+        assert_eq!(state.advance(), Some(Phase::Results));
+        assert_eq!(state.winner, Some(0));
+    }
+
+    #[test]
+    fn last_player_standing_wins() {
+        let mut state = playing(3, 10, 1);
+        assert_eq!(state.death(0), None);
+        assert_eq!(state.death(2), Some(1));
+        assert_eq!(state.advance(), Some(Phase::Results));
+        assert_eq!(state.winner, Some(1));
+    }
+
+    #[test]
+    fn solo_play_ends_with_no_winner() {
+        let mut state = playing(1, 10, 2);
+        assert_eq!(state.death(0), None);
+        assert_eq!(state.death(0), Some(NO_PLAYER));
+        assert_eq!(state.advance(), Some(Phase::Results));
+        assert_eq!(state.winner, Some(NO_PLAYER));
+    }
+
+    #[test]
+    fn results_lead_to_a_rematch() {
+        let mut state = playing(2, 1, 3);
+        state.death(1);
+        assert_eq!(state.goal(0), Some(0));
+        assert_eq!(state.advance(), Some(Phase::Results));
+        assert_eq!(state.advance(), None);
+        finish_phase(&mut state);
+        assert_eq!(state.advance(), Some(Phase::Ready));
+        assert_eq!(state.round, 1);
+        assert_eq!(state.ready, 0);
+        assert_eq!(state.winner, None);
+        for score in &state.scores[..2] {
+            assert_eq!((score.points, score.lives), (0, 3));
+        }
+    }
+
+    #[test]
+    fn unreadying_cancels_the_countdown() {
+        let mut state = joined(2, 10, 3);
+        state.ready = 0b11;
+        assert_eq!(state.advance(), Some(Phase::Countdown));
+        state.ready &= !(1 << 1);
+        assert_eq!(state.advance(), Some(Phase::Ready));
+    }
+}
@@ -1,16 +1,98 @@
+use controls::{sibling_exe, Action, Bindings, INPUT_NAMES};
 use eframe::egui;
 use std::process::Command;
 
+// Helper to get the name of the first key pressed this frame, if it can be bound
+fn pressed_key(ctx: &egui::Context) -> Option<&'static str> {
+    ctx.input(|i| {
+        i.events.iter().find_map(|event| match event {
+            egui::Event::Key {
+                key,
+                pressed: true,
+                repeat: false,
+                ..
+            } => INPUT_NAMES.iter().copied().find(|name| *name == key.name()),
+            _ => None,
+        })
+    })
+}
+
+// Helper to show the rebinding screen, each action with its inputs
+// Clicking an input unbinds it, + waits for a new key, mouse button or scroll to bind
+fn controls_ui(ui: &mut egui::Ui, bindings: &mut Bindings, listening: &mut Option<Action>) {
+    if let Some(action) = *listening {
+        ui.label(format!("Binding {}", action.label()));
+        let capture = ui.add_sized(
+            [ui.available_width(), 40.0],
+            egui::Button::new("Press a key, or use a mouse button or the wheel here"),
+        );
+        let buttons = [
+            (egui::PointerButton::Primary, "MouseLeft"),
+            (egui::PointerButton::Secondary, "MouseRight"),
+            (egui::PointerButton::Middle, "MouseMiddle"),
+            (egui::PointerButton::Extra1, "Mouse4"),
+            (egui::PointerButton::Extra2, "Mouse5"),
+        ];
+        let mut input = buttons
+            .into_iter()
+            .find(|(button, _)| capture.clicked_by(*button))
+            .map(|(_, name)| name);
+        if capture.hovered() {
+            let scroll = ui.input(|i| i.raw_scroll_delta.y);
+            if scroll > 0.0 {
+                input = Some("ScrollUp");
+            } else if scroll < 0.0 {
+                input = Some("ScrollDown");
+            }
+        }
+        if let Some(input) = input.or_else(|| pressed_key(ui.ctx())) {
+            bindings.add(action, input);
+            *listening = None;
+        }
+        if ui.button("Cancel").clicked() {
+            *listening = None;
+        }
+        return;
+    }
+
+    egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+        egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+            for action in Action::ALL {
+                ui.label(action.label());
+                ui.horizontal(|ui| {
+                    for input in bindings.get(action).to_vec() {
+                        if ui.small_button(&input).on_hover_text("Click to unbind").clicked() {
+                            bindings.remove(action, &input);
+                        }
+                    }
+                    if ui.small_button("+").on_hover_text("Add a binding").clicked() {
+                        *listening = Some(action);
+                    }
+                });
+                ui.end_row();
+            }
+        });
+    });
+}
+
 pub fn main() {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default().with_inner_size([320.0, 240.0]),
+        viewport: egui::ViewportBuilder::default().with_inner_size([480.0, 600.0]),
         ..Default::default()
     };
 
     // Our application state:
     let mut ip = "127.0.0.1".to_owned();
+    let (mut bindings, errors) = Bindings::load();
+    let mut status = errors
+        .iter()
+        .map(|e| format!("{}: {}", controls::bindings_path().display(), e))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut saved = bindings.clone();
+    let mut listening = None;
 
     let _ = eframe::run_simple_native("se-phere.io", options, move |ctx, _frame| {
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -22,10 +104,38 @@ pub fn main() {
             });
             ui.label(" ");
             if ui.button("Join Game").clicked() {
+                // the client reads the bindings file when it starts
+                if bindings != saved {
+                    if let Err(e) = bindings.save() {
+                        println!("Could not save controls: {}", e);
+                    }
+                }
                 let result = ip.clone();
-                let _ = Command::new("target/release/client").args([result]).spawn();
+                let _ = Command::new(sibling_exe("client")).args([result]).spawn();
                 ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
             }
+            ui.label(" ");
+            ui.collapsing("Controls", |ui| {
+                controls_ui(ui, &mut bindings, &mut listening);
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        status = match bindings.save() {
+                            Ok(()) => {
+                                saved = bindings.clone();
+                                format!("Saved to {}", controls::bindings_path().display())
+                            }
+                            Err(e) => format!("Could not save controls: {}", e),
+                        };
+                    }
+                    if ui.button("Reset to Defaults").clicked() {
+                        bindings = Bindings::default();
+                        listening = None;
+                    }
+                });
+                if !status.is_empty() {
+                    ui.label(&status);
+                }
+            });
         });
     });
 }